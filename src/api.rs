@@ -1,2 +1,4 @@
 pub mod login;
 pub mod auth;
+pub mod user;
+pub mod admin;
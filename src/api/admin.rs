@@ -0,0 +1,33 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::APIValue;
+use crate::DatabaseConnectionPool;
+use crate::HTTPRequest;
+use crate::HTTPResponse;
+use crate::api_response_json;
+use crate::api_database_error;
+use crate::rehash_plaintext_passwords;
+
+//hash all passwords, which are still stored in plaintext
+//the route requires the role 'admin'
+pub fn api_admin_rehash_passwords (
+    _request: &HTTPRequest, 
+    database_connections: Arc<DatabaseConnectionPool>,
+) -> HTTPResponse {
+
+    let updated = match rehash_plaintext_passwords(&database_connections) {
+        Ok(updated) => updated,
+        Err(error) => {
+            return api_database_error(error);
+        },
+    };
+
+    let mut api_response_btreemap: BTreeMap<String, Option<APIValue>> = BTreeMap::new();
+    api_response_btreemap.insert(
+        String::from("RehashedPasswords"), 
+        Some(APIValue::Number(updated as i32))
+    );
+
+    api_response_json(api_response_btreemap)
+}
@@ -1,69 +1,92 @@
 use std::collections::BTreeMap;
 use std::sync::Arc;
-use std::time::SystemTime;
-use std::time::UNIX_EPOCH;
 
 use crate::APIValue;
 use crate::JsonType;
 use crate::DatabaseConnectionPool;
 use crate::DatabaseValue;
+use crate::TokenCheck;
+use crate::check_token;
+use crate::hash_token;
+use crate::unix_time;
+use crate::create_session;
+use crate::database_value_i64;
+use crate::delete_session;
+use crate::delete_user_sessions;
+use crate::verify_dummy_password;
+use crate::LOGIN_THROTTLE;
+use crate::StatusCode;
+use crate::Cookie;
+use crate::SESSION_COOKIE;
+use crate::session_cookie;
+use crate::session_cookie_removal;
+use crate::csrf_cookie;
+use crate::CSRF_COOKIE;
+use crate::constant_time_eq;
 use crate::HTTPRequest;
 use crate::generate_token;
-use crate::api_send_response_json;
-use crate::parse_json_string;
+use crate::HTTPResponse;
+use crate::api_response_json;
+use crate::api_database_error;
+use crate::api_error;
+use crate::parse_json;
+use crate::request_token;
+use crate::PasswordCheck;
+use crate::hash_password;
+use crate::verify_password;
+use crate::middleware::unauthorized;
 
 //function for auto login of user
 pub fn api_login_auto_logon (
-    request: HTTPRequest, 
+    request: &HTTPRequest, 
     database_connections: Arc<DatabaseConnectionPool>,
-) {
-    let post_data = parse_json_string(&request.body);
-
-    let user_token = match post_data.get("UserToken").unwrap() {
-        JsonType::String(token) => token.to_string(),
-        _ => String::from(""),
-    };
+) -> HTTPResponse {
+    //the token is sent in the body, as bearer token or as session cookie
+    let user_token = request_token(request);
 
 
     //create variables for the response
-    let mut api_response_vector: Vec<BTreeMap<String, Option<APIValue>>> = Vec::new();
     let mut api_response_btreemap: BTreeMap<String, Option<APIValue>> = BTreeMap::new();
 
-    //see if a user with this token is in the database
-    let query = format!(
-        "SELECT token, rememberlogin FROM users WHERE token = '{}'", 
-        user_token
-    );
-
-    let mut db_con = DatabaseConnectionPool::get_connection(&database_connections).unwrap();
-    let data = db_con.query(&query);
-    database_connections.release_connection(db_con);
+    //see if the token belongs to a user and has not expired
+    let token_check = match check_token(&database_connections, &user_token) {
+        Ok(token_check) => token_check,
+        Err(error) => {
+            return api_database_error(error);
+        },
+    };
 
     //check if no user has been found, if yes, return
-    if data.len() == 0 {
-        api_response_btreemap.insert(
-            String::from("AuthenticationSuccessfull"), 
-            Some(APIValue::Boolean(false))
-        );
-        api_response_btreemap.insert(
-            String::from("Message"), 
-            Some(APIValue::String(String::from("No user found with matching token")))
-        );
-
-        api_response_vector.push(api_response_btreemap); 
-        api_send_response_json(request, api_response_vector);
-        
-        return;
-    }
-
-    //check if the user wants to logged in automatically
-    let user_remember_login = match data[0].get("rememberlogin").unwrap() {
-        Some(DatabaseValue::Boolean(true)) => true,
-        Some(DatabaseValue::Boolean(false)) => false,
-        _ => false,
+    let user = match token_check {
+        TokenCheck::Valid(user) => user,
+        TokenCheck::Unknown => {
+            api_response_btreemap.insert(
+                String::from("AuthenticationSuccessfull"), 
+                Some(APIValue::Boolean(false))
+            );
+            api_response_btreemap.insert(
+                String::from("Message"), 
+                Some(APIValue::String(String::from("No user found with matching token")))
+            );
+
+            return api_response_json(api_response_btreemap);
+        },
+        _ => {
+            api_response_btreemap.insert(
+                String::from("AuthenticationSuccessfull"), 
+                Some(APIValue::Boolean(false))
+            );
+            api_response_btreemap.insert(
+                String::from("Message"), 
+                Some(APIValue::String(String::from(token_check.message())))
+            );
+
+            return api_response_json(api_response_btreemap);
+        },
     };
 
-    if user_remember_login {
+    //check if the user wants to logged in automatically
+    if user.remember_login {
         api_response_btreemap.insert(
             String::from("AuthenticationSuccessfull"), 
             Some(APIValue::Boolean(true))
@@ -73,10 +96,7 @@ pub fn api_login_auto_logon (
             Some(APIValue::String(String::from("Matching user with token has been found")))
         );
 
-        api_response_vector.push(api_response_btreemap); 
-        api_send_response_json(request, api_response_vector);
-        
-        return;
+        api_response_json(api_response_btreemap)
 
     } else {
         api_response_btreemap.insert(
@@ -88,121 +108,298 @@ pub fn api_login_auto_logon (
             Some(APIValue::String(String::from("Matching user with token has been found, but remember login is not active")))
         );
 
-        api_response_vector.push(api_response_btreemap); 
-        api_send_response_json(request, api_response_vector);
-        
-        return;
+        api_response_json(api_response_btreemap)
     }
 }
 
 pub fn api_login_logon (    
-    request: HTTPRequest, 
+    request: &HTTPRequest, 
     database_connections: Arc<DatabaseConnectionPool>,
-) {
+) -> HTTPResponse {
 
     //parse the data from the fetch request
-    let post_data = parse_json_string(&request.body);
+    let post_data = match parse_json(&request.body) {
+        Ok(post_data) => post_data,
+        Err(error) => {
+            return api_error(&format!("Invalid json: {}", error));
+        },
+    };
 
     //initalizse the paramters
     let mut user = String::from("");
     let mut user_pw = String::from("");
     let mut remember: bool = false;
+    let mut use_cookie: bool = false;
 
     //check the data from the http request, if data is available
-    if let JsonType::String(json_user) = post_data.get("user").unwrap() {
+    if let Some(JsonType::String(json_user)) = post_data.get("user") {
         user = json_user.to_string()
     }; 
 
-    if let JsonType::String(json_pw) = post_data.get("password").unwrap() {
+    if let Some(JsonType::String(json_pw)) = post_data.get("password") {
         user_pw = json_pw.to_string()
     };
 
-    if let JsonType::Boolean(json_remember) = post_data.get("remember").unwrap() {
+    if let Some(JsonType::Boolean(json_remember)) = post_data.get("remember") {
         remember = *json_remember
     }; 
 
-    let mut api_response_vector: Vec<BTreeMap<String, Option<APIValue>>> = Vec::new();
-    let mut api_response_btreemap: BTreeMap<String, Option<APIValue>> = BTreeMap::new();
-
-    let query = format!(
-        "SELECT id, password FROM users WHERE username = '{}'", 
-        user
-    );
+    //the token is sent as HttpOnly cookie instead of in the response body
+    if let Some(JsonType::Boolean(json_cookie)) = post_data.get("cookie") {
+        use_cookie = *json_cookie
+    }; 
 
-    let mut db_con = DatabaseConnectionPool::get_connection(&database_connections).unwrap();
-    let data = db_con.query(&query);
-    database_connections.release_connection(db_con);
+    let mut api_response_btreemap: BTreeMap<String, Option<APIValue>> = BTreeMap::new();
+    let mut cookies: Vec<Cookie> = Vec::new();
 
-    //check if a user has been found
-    //if not, return
-    if data.len() == 0 {
+    //address of the client for counting failed logins
+    let client_ip = match request.stream.peer_addr() {
+        Ok(address) => address.ip().to_string(),
+        Err(_) => String::from("unknown"),
+    };
 
+    //after too many failed logins the client has to wait, the password is not checked
+    if let Some(retry_after) = LOGIN_THROTTLE.retry_after(&user, &client_ip) {
         api_response_btreemap.insert(
             String::from("LoginSuccessfull"), 
             Some(APIValue::Boolean(false))
         );
         api_response_btreemap.insert(
             String::from("Message"), 
-            Some(APIValue::String(String::from("User not found")))
+            Some(APIValue::String(String::from("Too many failed logins, try again later")))
         );
-        api_response_vector.push(api_response_btreemap); 
 
-        api_send_response_json(request, api_response_vector);
-        
-        return;
+        let mut response = api_response_json(api_response_btreemap)
+            .header("Retry-After", &(retry_after.as_secs() + 1).to_string());
+        response.status = StatusCode::TooManyRequests;
+
+        return response;
     }
 
-    //get password from database response
-    let db_pw = match data[0].get("password").unwrap() {
-        Some(DatabaseValue::Varchar(pw)) => pw.clone(),
-        _ => String::from(""),
+    let query = "SELECT id, password FROM users WHERE username = $1";
+
+    let mut db_con = DatabaseConnectionPool::get_connection(&database_connections).unwrap();
+    let data = db_con.query_params(query, &[&user]);
+    database_connections.release_connection(db_con);
+
+    let data = match data {
+        Ok(data) => data,
+        Err(error) => {
+            return api_database_error(error);
+        },
     };
 
+    //check if a user has been found
+    //if not, a password is checked anyway, so the response time is the same
+    let password_check = match data.first() {
+        Some(row) => {
+            //get password from database response
+            let db_pw = match row.get("password") {
+                Some(Some(DatabaseValue::Varchar(pw))) => pw.clone(),
+                _ => String::from(""),
+            };
+
+            verify_password(&user_pw, &db_pw)
+        },
+        None => {
+            verify_dummy_password(&user_pw);
+            PasswordCheck::Invalid
+        },
+    };
+
+    //passwords in plaintext or with old parameters are replaced with a new hash
+    if password_check == PasswordCheck::ValidNeedsRehash {
+        match hash_password(&user_pw) {
+            Ok(hash) => {
+                let query = "UPDATE users SET password = $1 WHERE username = $2";
+
+                let mut db_con = DatabaseConnectionPool::get_connection(&database_connections).unwrap();
+                let result = db_con.query_params(query, &[&hash, &user]);
+                database_connections.release_connection(db_con);
+
+                if let Err(error) = result {
+                    return api_database_error(error);
+                }
+            },
+            Err(err) => println!("could not rehash password: {}", err),
+        }
+    }
+
     //check if the password matches
-    if db_pw == user_pw.as_str() {//TODO when pw matches
+    if password_check != PasswordCheck::Invalid {
+
+        LOGIN_THROTTLE.record_success(&user, &client_ip);
 
         api_response_btreemap.insert(
             String::from("LoginSuccessfull"), 
             Some(APIValue::Boolean(true))
         );
 
-        //generate new token
-        let user_token = generate_token();
+        let user_id = database_value_i64(data[0].get("id")).unwrap_or(0);
+
+        //start a new session, the sessions on other devices stay valid
+        let user_token = match create_session(&database_connections, user_id, remember) {
+            Ok(user_token) => user_token,
+            Err(error) => {
+                return api_database_error(error);
+            },
+        };
+
+        //insert token into btreemap or cookie
+        //requests with the session cookie need the csrf token from the second cookie
+        if use_cookie {
+            cookies.push(session_cookie(&user_token, remember));
+            cookies.push(csrf_cookie(remember));
+        } else {
+            api_response_btreemap.insert(
+                String::from("UserToken"), 
+                Some(APIValue::String(user_token))
+            );
+        }
 
-        //insert token into btreemap
+    } else {
+        LOGIN_THROTTLE.record_failure(&user, &client_ip);
+
+        //the same message for unknown users and wrong passwords, so usernames can not be guessed
+        api_response_btreemap.insert(
+            String::from("LoginSuccessfull"), 
+            Some(APIValue::Boolean(false))
+        );
         api_response_btreemap.insert(
-            String::from("UserToken"), 
-            Some(APIValue::String(user_token.to_string()))
+            String::from("Message"), 
+            Some(APIValue::String(String::from("Incorrect username or password")))
         );
+    }
 
-        let token_creation_timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    cookies
+        .iter()
+        .fold(api_response_json(api_response_btreemap), |response, cookie| response.cookie(cookie))
 
-        let query = format!(
-            "UPDATE users SET token = '{}', tokencrtime = {}, rememberlogin = {} WHERE username = '{}'", 
-            user_token,
-            token_creation_timestamp,
-            remember,
-            user,
-        );
+}
+
+//replace a valid token with a new one, the old token can not be used anymore
+pub fn api_login_refresh (
+    request: &HTTPRequest, 
+    database_connections: Arc<DatabaseConnectionPool>,
+) -> HTTPResponse {
+    //only tokens, which are still valid, can be refreshed, they are checked by the middleware
+    let principal = match &request.principal {
+        Some(principal) => principal,
+        None => return unauthorized(TokenCheck::Unknown.message()),
+    };
+
+    //the token is sent in the body, as bearer token or as session cookie
+    let user_token = request_token(request);
 
-        let mut db_con = DatabaseConnectionPool::get_connection(&database_connections).unwrap();
-        db_con.query(&query);
-        database_connections.release_connection(db_con);
+    let mut api_response_btreemap: BTreeMap<String, Option<APIValue>> = BTreeMap::new();
+
+    //a token from the session cookie is replaced in the cookie
+    let from_cookie = match request.cookie(SESSION_COOKIE) {
+        Some(cookie_token) => constant_time_eq(cookie_token.as_bytes(), user_token.as_bytes()),
+        None => false,
+    };
 
-    } else {//TODO what to do, when password does not match
+    let new_token = generate_token();
+    let now = unix_time();
+
+    //the old token is part of the condition, so two refreshes with the same token
+    //can not both succeed
+    //the creation time is kept, so refreshing does not extend the absolute lifetime of the session
+    let query = "UPDATE sessions SET token = $1, lastused = $2 WHERE token = $3 RETURNING id";
+
+    let mut db_con = DatabaseConnectionPool::get_connection(&database_connections).unwrap();
+    let result = db_con.query_params(query, &[&hash_token(&new_token), &now, &hash_token(&user_token)]);
+    database_connections.release_connection(db_con);
+
+    let data = match result {
+        Ok(data) => data,
+        Err(error) => {
+            return api_database_error(error);
+        },
+    };
+
+    if data.is_empty() {
         api_response_btreemap.insert(
-            String::from("LoginSuccessfull"), 
+            String::from("RefreshSuccessfull"), 
             Some(APIValue::Boolean(false))
         );
         api_response_btreemap.insert(
             String::from("Message"), 
-            Some(APIValue::String(String::from("Incorrect password")))
+            Some(APIValue::String(String::from(TokenCheck::Unknown.message())))
         );
+
+        return api_response_json(api_response_btreemap);
     }
 
-    api_response_vector.push(api_response_btreemap); 
+    api_response_btreemap.insert(
+        String::from("RefreshSuccessfull"), 
+        Some(APIValue::Boolean(true))
+    );
 
-    crate::api_send_response_json(request, api_response_vector);
+    if from_cookie {
+        return api_response_json(api_response_btreemap)
+            .cookie(&session_cookie(&new_token, principal.remember_login))
+            .cookie(&csrf_cookie(principal.remember_login));
+    }
 
+    api_response_btreemap.insert(
+        String::from("UserToken"), 
+        Some(APIValue::String(new_token))
+    );
+
+    api_response_json(api_response_btreemap)
+}
+
+//end the session of the token
+pub fn api_login_logout (
+    request: &HTTPRequest, 
+    database_connections: Arc<DatabaseConnectionPool>,
+) -> HTTPResponse {
+    api_login_end_sessions(request, database_connections, false)
 }
 
+//end every session of the user, to whom the token belongs
+pub fn api_login_logout_all (
+    request: &HTTPRequest, 
+    database_connections: Arc<DatabaseConnectionPool>,
+) -> HTTPResponse {
+    api_login_end_sessions(request, database_connections, true)
+}
+
+fn api_login_end_sessions (
+    request: &HTTPRequest, 
+    database_connections: Arc<DatabaseConnectionPool>,
+    all_sessions: bool,
+) -> HTTPResponse {
+    //the token has been checked by the middleware
+    let principal = match &request.principal {
+        Some(principal) => principal,
+        None => return unauthorized(TokenCheck::Unknown.message()),
+    };
+
+    let mut api_response_btreemap: BTreeMap<String, Option<APIValue>> = BTreeMap::new();
+
+    let result = match all_sessions {
+        true => delete_user_sessions(&database_connections, principal.user_id).map(|_| ()),
+        false => delete_session(&database_connections, principal.session_id),
+    };
+
+    if let Err(error) = result {
+        return api_database_error(error);
+    }
+
+    api_response_btreemap.insert(
+        String::from("LogoutSuccessfull"), 
+        Some(APIValue::Boolean(true))
+    );
+
+    let response = api_response_json(api_response_btreemap);
+
+    //the browser deletes the session and csrf cookie
+    match request.cookie(SESSION_COOKIE) {
+        Some(_) => response
+            .cookie(&session_cookie_removal())
+            .cookie(&Cookie::removal(CSRF_COOKIE).path("/")),
+        None => response,
+    }
+}
@@ -0,0 +1,236 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::APIValue;
+use crate::JsonType;
+use crate::DatabaseConnectionPool;
+use crate::DatabaseValue;
+use crate::HTTPRequest;
+use crate::HTTPResponse;
+use crate::api_response_json;
+use crate::api_database_error;
+use crate::api_error;
+use crate::parse_json;
+use crate::PasswordCheck;
+use crate::TokenCheck;
+use crate::hash_password;
+use crate::verify_password;
+use crate::middleware::unauthorized;
+
+const USERNAME_MIN_LENGTH: usize = 3;
+const USERNAME_MAX_LENGTH: usize = 32;
+const PASSWORD_MIN_LENGTH: usize = 8;
+const PASSWORD_MAX_LENGTH: usize = 128;
+
+//returns the reason, why the username can not be used
+fn validate_username (username: &str) -> Option<String> {
+    let length = username.chars().count();
+
+    if !(USERNAME_MIN_LENGTH..=USERNAME_MAX_LENGTH).contains(&length) {
+        return Some(format!(
+            "Username must be between {} and {} characters long", 
+            USERNAME_MIN_LENGTH, 
+            USERNAME_MAX_LENGTH
+        ));
+    }
+
+    if !username.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.') {
+        return Some(String::from("Username may only contain letters, digits, '_', '-' and '.'"));
+    }
+
+    None
+}
+
+//returns the reason, why the password can not be used
+fn validate_password (password: &str) -> Option<String> {
+    let length = password.chars().count();
+
+    if !(PASSWORD_MIN_LENGTH..=PASSWORD_MAX_LENGTH).contains(&length) {
+        return Some(format!(
+            "Password must be between {} and {} characters long", 
+            PASSWORD_MIN_LENGTH, 
+            PASSWORD_MAX_LENGTH
+        ));
+    }
+
+    None
+}
+
+fn json_string (post_data: &JsonType, key: &str) -> String {
+    match post_data.get(key) {
+        Some(JsonType::String(value)) => value.to_string(),
+        _ => String::from(""),
+    }
+}
+
+pub fn api_user_register (
+    request: &HTTPRequest, 
+    database_connections: Arc<DatabaseConnectionPool>,
+) -> HTTPResponse {
+
+    //parse the data from the fetch request
+    let post_data = match parse_json(&request.body) {
+        Ok(post_data) => post_data,
+        Err(error) => {
+            return api_error(&format!("Invalid json: {}", error));
+        },
+    };
+
+    let user = json_string(&post_data, "user");
+    let user_pw = json_string(&post_data, "password");
+
+    let mut api_response_btreemap: BTreeMap<String, Option<APIValue>> = BTreeMap::new();
+
+    //check the data before anything is written to the database
+    if let Some(message) = validate_username(&user).or_else(|| validate_password(&user_pw)) {
+        api_response_btreemap.insert(
+            String::from("RegistrationSuccessfull"), 
+            Some(APIValue::Boolean(false))
+        );
+        api_response_btreemap.insert(
+            String::from("Message"), 
+            Some(APIValue::String(message))
+        );
+
+        return api_response_json(api_response_btreemap);
+    }
+
+    let hash = match hash_password(&user_pw) {
+        Ok(hash) => hash,
+        Err(err) => {
+            println!("could not hash password: {}", err);
+            return api_error("Password could not be stored");
+        },
+    };
+
+    let query = "INSERT INTO users (username, password) VALUES ($1, $2)";
+
+    let mut db_con = DatabaseConnectionPool::get_connection(&database_connections).unwrap();
+    let result = db_con.query_params(query, &[&user, &hash]);
+    database_connections.release_connection(db_con);
+
+    match result {
+        Ok(_) => {
+            api_response_btreemap.insert(
+                String::from("RegistrationSuccessfull"), 
+                Some(APIValue::Boolean(true))
+            );
+        },
+        //the unique constraint on the username detects duplicates
+        //checking before the insert would not work with two registrations at the same time
+        Err(error) if error.is_unique_violation() => {
+            api_response_btreemap.insert(
+                String::from("RegistrationSuccessfull"), 
+                Some(APIValue::Boolean(false))
+            );
+            api_response_btreemap.insert(
+                String::from("Message"), 
+                Some(APIValue::String(String::from("Username is already taken")))
+            );
+        },
+        Err(error) => {
+            return api_database_error(error);
+        },
+    }
+
+    api_response_json(api_response_btreemap)
+}
+
+pub fn api_user_change_password (
+    request: &HTTPRequest, 
+    database_connections: Arc<DatabaseConnectionPool>,
+) -> HTTPResponse {
+
+    //parse the data from the fetch request
+    let post_data = match parse_json(&request.body) {
+        Ok(post_data) => post_data,
+        Err(error) => {
+            return api_error(&format!("Invalid json: {}", error));
+        },
+    };
+
+    let user_pw = json_string(&post_data, "password");
+    let new_pw = json_string(&post_data, "new_password");
+
+    let mut api_response_btreemap: BTreeMap<String, Option<APIValue>> = BTreeMap::new();
+
+    //the password can only be changed with a valid token, it is checked by the middleware
+    let principal = match &request.principal {
+        Some(principal) => principal,
+        None => return unauthorized(TokenCheck::Unknown.message()),
+    };
+
+    let query = "SELECT password FROM users WHERE id = $1";
+
+    let mut db_con = DatabaseConnectionPool::get_connection(&database_connections).unwrap();
+    let data = db_con.query_params(query, &[&principal.user_id]);
+    database_connections.release_connection(db_con);
+
+    let data = match data {
+        Ok(data) => data,
+        Err(error) => {
+            return api_database_error(error);
+        },
+    };
+
+    let db_pw = match data.first().and_then(|row| row.get("password")) {
+        Some(Some(DatabaseValue::Varchar(pw))) => pw.clone(),
+        _ => String::from(""),
+    };
+
+    //the current password has to be known as well, a stolen token is not enough
+    let message = if verify_password(&user_pw, &db_pw) == PasswordCheck::Invalid {
+        Some(String::from("Incorrect password"))
+    } else {
+        validate_password(&new_pw)
+    };
+
+    if let Some(message) = message {
+        api_response_btreemap.insert(
+            String::from("PasswordChanged"), 
+            Some(APIValue::Boolean(false))
+        );
+        api_response_btreemap.insert(
+            String::from("Message"), 
+            Some(APIValue::String(message))
+        );
+
+        return api_response_json(api_response_btreemap);
+    }
+
+    let hash = match hash_password(&new_pw) {
+        Ok(hash) => hash,
+        Err(err) => {
+            println!("could not hash password: {}", err);
+            return api_error("Password could not be stored");
+        },
+    };
+
+    let query = "UPDATE users SET password = $1 WHERE id = $2";
+
+    let mut db_con = DatabaseConnectionPool::get_connection(&database_connections).unwrap();
+    let result = db_con.query_params(query, &[&hash, &principal.user_id]);
+    database_connections.release_connection(db_con);
+
+    if let Err(error) = result {
+        return api_database_error(error);
+    }
+
+    //sessions on other devices may have been started with the old password
+    let query = "DELETE FROM sessions WHERE userid = $1 AND id <> $2";
+
+    let mut db_con = DatabaseConnectionPool::get_connection(&database_connections).unwrap();
+    let result = db_con.query_params(query, &[&principal.user_id, &principal.session_id]);
+    database_connections.release_connection(db_con);
+
+    if let Err(error) = result {
+        return api_database_error(error);
+    }
+
+    api_response_btreemap.insert(
+        String::from("PasswordChanged"), 
+        Some(APIValue::Boolean(true))
+    );
+
+    api_response_json(api_response_btreemap)
+}
@@ -1 +1 @@
-pub const ROOT: &'static str = "/var/www/memeoff2";
+pub const ROOT: &str = "/var/www/memeoff2";
@@ -1,24 +1,211 @@
 use std::{
-    sync::{mpsc, Arc, Mutex, Condvar},
+    sync::{mpsc, Arc, Mutex, RwLock, Condvar, LazyLock},
+    sync::atomic::{AtomicUsize, Ordering},
     io::BufReader,
     io::BufRead,
     io::Write,
     io::Read,
+    io::Seek,
+    io::SeekFrom,
     io::ErrorKind,
-    net::TcpStream,
+    net::{Shutdown, TcpStream},
     thread,
+    panic::{self, AssertUnwindSafe},
     collections::{HashMap, VecDeque, BTreeMap},
     fs::File,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use rand::{distributions::Alphanumeric, Rng, RngCore};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64, engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL};
+use hmac::{Hmac, Mac};
+use md5::Md5;
+use sha2::{Digest, Sha256};
+use argon2::{
+    Argon2,
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString, rand_core::OsRng},
 };
-use rand::{distributions::Alphanumeric, Rng};
 
 mod constants;
 mod api;
+pub mod middleware;
 
 pub trait MatchJsonType {
     fn match_json_type(&self) -> JsonType;
 }
 
+//values which can be sent to the database as bind parameters
+//the value is transmitted in text format, None is sent as sql null
+pub trait ToSql {
+    fn to_sql_text(&self) -> Option<String>;
+    //oid of the parameter type, 0 lets the database infer the type
+    fn type_oid(&self) -> i32;
+}
+
+impl ToSql for String {
+    fn to_sql_text(&self) -> Option<String> {
+        Some(self.clone())
+    }
+    fn type_oid(&self) -> i32 {
+        1043
+    }
+}
+
+impl ToSql for &str {
+    fn to_sql_text(&self) -> Option<String> {
+        Some(self.to_string())
+    }
+    fn type_oid(&self) -> i32 {
+        1043
+    }
+}
+
+impl ToSql for i32 {
+    fn to_sql_text(&self) -> Option<String> {
+        Some(self.to_string())
+    }
+    fn type_oid(&self) -> i32 {
+        23
+    }
+}
+
+impl ToSql for i64 {
+    fn to_sql_text(&self) -> Option<String> {
+        Some(self.to_string())
+    }
+    fn type_oid(&self) -> i32 {
+        20
+    }
+}
+
+impl ToSql for bool {
+    fn to_sql_text(&self) -> Option<String> {
+        match self {
+            true => Some(String::from("t")),
+            false => Some(String::from("f")),
+        }
+    }
+    fn type_oid(&self) -> i32 {
+        16
+    }
+}
+
+impl ToSql for DatabaseValue {
+    fn to_sql_text(&self) -> Option<String> {
+        match self {
+            DatabaseValue::Integer(int) => int.to_sql_text(),
+            DatabaseValue::BigInteger(int) => int.to_sql_text(),
+            DatabaseValue::Varchar(string) => string.to_sql_text(),
+            DatabaseValue::Boolean(bool) => bool.to_sql_text(),
+        }
+    }
+    fn type_oid(&self) -> i32 {
+        match self {
+            DatabaseValue::Integer(int) => int.type_oid(),
+            DatabaseValue::BigInteger(int) => int.type_oid(),
+            DatabaseValue::Varchar(string) => string.type_oid(),
+            DatabaseValue::Boolean(bool) => bool.type_oid(),
+        }
+    }
+}
+
+impl<T: ToSql> ToSql for Option<T> {
+    fn to_sql_text(&self) -> Option<String> {
+        match self {
+            Some(value) => value.to_sql_text(),
+            None => None,
+        }
+    }
+    fn type_oid(&self) -> i32 {
+        match self {
+            Some(value) => value.type_oid(),
+            None => 0,
+        }
+    }
+}
+
+pub type DatabaseRow = BTreeMap<String, Option<DatabaseValue>>;
+
+//severity of an error or notice, as sent by the database
+#[derive(Debug, Default, PartialEq)]
+pub enum DatabaseSeverity {
+    #[default]
+    Error,
+    Fatal,
+    Panic,
+    Warning,
+    Notice,
+    Debug,
+    Info,
+    Log,
+}
+
+impl DatabaseSeverity {
+    pub fn parse (severity: &str) -> DatabaseSeverity {
+        match severity {
+            "FATAL" => DatabaseSeverity::Fatal,
+            "PANIC" => DatabaseSeverity::Panic,
+            "WARNING" => DatabaseSeverity::Warning,
+            "NOTICE" => DatabaseSeverity::Notice,
+            "DEBUG" => DatabaseSeverity::Debug,
+            "INFO" => DatabaseSeverity::Info,
+            "LOG" => DatabaseSeverity::Log,
+            _ => DatabaseSeverity::Error,
+        }
+    }
+}
+
+//fields of an error or notice response from the database
+//https://www.postgresql.org/docs/current/protocol-error-fields.html
+#[derive(Debug, Default)]
+pub struct DatabaseError {
+    pub severity: DatabaseSeverity,
+    pub code: String,
+    pub message: String,
+    pub detail: Option<String>,
+    pub hint: Option<String>,
+    pub position: Option<u32>,
+}
+
+impl std::fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?} {}: {}", self.severity, self.code, self.message)?;
+
+        if let Some(detail) = &self.detail {
+            write!(f, ", detail: {}", detail)?;
+        }
+
+        if let Some(hint) = &self.hint {
+            write!(f, ", hint: {}", hint)?;
+        }
+
+        if let Some(position) = &self.position {
+            write!(f, ", position: {}", position)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for DatabaseError {}
+
+//sqlstate of an insert or update, which violates a unique constraint
+pub const SQLSTATE_UNIQUE_VIOLATION: &str = "23505";
+
+impl DatabaseError {
+    pub fn is_unique_violation (&self) -> bool {
+        self.code == SQLSTATE_UNIQUE_VIOLATION
+    }
+}
+
+//notices have the same fields as errors, but do not end the query
+pub type DatabaseNotice = DatabaseError;
+
+//default hook for notices, writes them to the log
+pub fn log_database_notice (notice: &DatabaseNotice) {
+    println!("database notice: {}", notice);
+}
+
 pub struct DatabaseRowDescription {
     name: String,
     #[allow(dead_code)]
@@ -39,12 +226,33 @@ pub enum ContentType {
     ApplicationJson,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Method {
     GET,
     POST,
+    PUT,
+    DELETE,
+    PATCH,
+    HEAD,
+    OPTIONS,
     Undefined,
 }
 
+impl Method {
+    pub fn as_str (&self) -> &'static str {
+        match self {
+            Method::GET => "GET",
+            Method::POST => "POST",
+            Method::PUT => "PUT",
+            Method::DELETE => "DELETE",
+            Method::PATCH => "PATCH",
+            Method::HEAD => "HEAD",
+            Method::OPTIONS => "OPTIONS",
+            Method::Undefined => "UNDEFINED",
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum DatabaseValue {
     Integer(i32),
@@ -64,15 +272,77 @@ impl MatchJsonType for DatabaseValue {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum JsonType {
     String(String),
     Number(i32),
     BigNumber(i64),
+    Float(f64),
     Boolean(bool),
+    Array(Vec<JsonType>),
+    Object(BTreeMap<String, JsonType>),
     Null,
 }
 
+impl MatchJsonType for JsonType {
+    fn match_json_type(&self) -> JsonType {
+        self.clone()
+    }
+}
+
+//a missing value is encoded as null
+impl<T: MatchJsonType> MatchJsonType for Option<T> {
+    fn match_json_type(&self) -> JsonType {
+        match self {
+            Some(value) => value.match_json_type(),
+            None => JsonType::Null,
+        }
+    }
+}
+
+//a map is encoded as json object, for example a single database row
+impl<T: MatchJsonType> MatchJsonType for BTreeMap<String, T> {
+    fn match_json_type(&self) -> JsonType {
+        JsonType::Object(
+            self.iter()
+                .map(|(key, value)| (key.clone(), value.match_json_type()))
+                .collect()
+        )
+    }
+}
+
+//a vector is always encoded as json array, even if it holds only one value
+impl<T: MatchJsonType> MatchJsonType for Vec<T> {
+    fn match_json_type(&self) -> JsonType {
+        JsonType::Array(self.iter().map(|value| value.match_json_type()).collect())
+    }
+}
+
+impl JsonType {
+    //get the value of a key, if the value is an object
+    pub fn get (&self, key: &str) -> Option<&JsonType> {
+        match self {
+            JsonType::Object(object) => object.get(key),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct JsonError {
+    pub message: String,
+    //position in the json string where the error occured
+    pub offset: usize,
+}
+
+impl std::fmt::Display for JsonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} at byte {}", self.message, self.offset)
+    }
+}
+
+impl std::error::Error for JsonError {}
+
 pub enum APIValue {
     String(String),
     Number(i32),
@@ -92,531 +362,3221 @@ impl MatchJsonType for APIValue {
 pub struct HTTPRequest {
     pub stream: TcpStream,
     pub request_line: RequestLine,
+    pub headers: HeaderMap,
     pub body: String,
+    //fields sent after a chunked body, kept apart from the headers
+    //so they can not replace headers, which were checked before the body was read
+    pub trailers: HeaderMap,
+    //if the connection stays open for further requests after the response
+    pub keep_alive: bool,
+    //values of the parameters in the route pattern, for example 'id' in '/api/users/:id'
+    pub path_params: HashMap<String, String>,
+    //user who sent the request, set by the authentication middleware
+    pub principal: Option<Principal>,
 }
 
-pub struct RequestLine {
-    pub empty: bool,
-    pub method: Method,
-    pub path: String,
-    pub query_string: Option<String>,
-    pub protocol: String,
-}
+impl HTTPRequest {
+    //connection header for the response, tells the client if the connection stays open
+    pub fn connection_header (&self) -> &str {
+        match self.keep_alive {
+            true => "keep-alive",
+            false => "close",
+        }
+    }
 
-impl RequestLine {
-    pub fn empty () -> RequestLine {
+    //value of a cookie sent by the client
+    pub fn cookie (&self, name: &str) -> Option<String> {
+        self.headers
+            .get_all("Cookie")
+            .into_iter()
+            .find_map(|header| parse_cookies(header).remove(name))
+    }
+}
 
-        let empty_request = RequestLine {
-            empty: true,
-            method: Method::Undefined,
-            path: String::new(),
-            query_string: None,
-            protocol: String::new(),
-        };
+//split the cookie header 'name1=value1; name2=value2' into name and value
+//if a name appears more than once, the first value is used
+pub fn parse_cookies (header: &str) -> HashMap<String, String> {
+    let mut cookies = HashMap::new();
 
-        empty_request
+    for pair in header.split(';') {
+        if let Some((name, value)) = pair.split_once('=') {
+            let name = name.trim();
+            //values may be enclosed in double quotes
+            let value = value.trim();
+            let value = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')).unwrap_or(value);
 
+            if !name.is_empty() {
+                cookies.entry(name.to_string()).or_insert_with(|| value.to_string());
+            }
+        }
     }
+
+    cookies
 }
 
-pub fn read_request_line (buf_reader: &mut BufReader<&TcpStream>) -> String {
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
 
-    //create new string for the request line
-    let mut request_line = String::new();
+//cookie for the Set-Cookie header of a response
+#[derive(Debug, Clone)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    pub path: Option<String>,
+    //seconds until the cookie expires, without it the cookie is removed when the browser closes
+    pub max_age: Option<i64>,
+    pub http_only: bool,
+    pub secure: bool,
+    pub same_site: Option<SameSite>,
+}
 
-    //read the line into the new string
-    //hint: connection reset by peer
-    match buf_reader.read_line(&mut request_line) {
-        Ok(_) => {},
-        Err(err) => println!("error: {}", err), 
+impl Cookie {
+    pub fn new (name: &str, value: &str) -> Cookie {
+        Cookie {
+            name: name.to_string(),
+            value: value.to_string(),
+            path: None,
+            max_age: None,
+            http_only: false,
+            secure: false,
+            same_site: None,
+        }
     }
 
+    pub fn path (mut self, path: &str) -> Cookie {
+        self.path = Some(path.to_string());
+        self
+    }
 
-    let request_line = request_line.trim().to_string();
+    pub fn max_age (mut self, max_age: i64) -> Cookie {
+        self.max_age = Some(max_age);
+        self
+    }
 
-    request_line
-}
+    //the cookie can not be read by javascript
+    pub fn http_only (mut self, http_only: bool) -> Cookie {
+        self.http_only = http_only;
+        self
+    }
 
-pub fn parse_request_line (request_line: String) -> RequestLine {
+    //the cookie is only sent over https
+    pub fn secure (mut self, secure: bool) -> Cookie {
+        self.secure = secure;
+        self
+    }
 
-    //if the stream does not send a correct request line, then stop handling the connetion
-    if request_line.is_empty() {
-        println!("---------request line was empty");
-        return RequestLine::empty();
+    pub fn same_site (mut self, same_site: SameSite) -> Cookie {
+        self.same_site = Some(same_site);
+        self
     }
 
-    //split status line by space
-    let mut request_line_split_iter = request_line.split_whitespace();
+    //cookie, which tells the browser to delete the cookie with this name
+    pub fn removal (name: &str) -> Cookie {
+        Cookie::new(name, "").max_age(0)
+    }
 
-    //extract the method from the http request
-    let method = match request_line_split_iter.next().unwrap() {
-            "GET" => Method::GET,
-            "POST" => Method::POST,
-            _ => Method::Undefined,
-    };
+    //value of the Set-Cookie header
+    pub fn header_value (&self) -> String {
+        let mut value = format!("{}={}", self.name, self.value);
 
-    //extract the path from the http request
-    let path = request_line_split_iter.next().unwrap();
+        if let Some(path) = &self.path {
+            value.push_str(&format!("; Path={}", path));
+        }
 
-    //split the path by '?' to extract any information from the path
-    let mut path_split = path.split('?');
+        if let Some(max_age) = self.max_age {
+            value.push_str(&format!("; Max-Age={}", max_age));
+        }
 
-    //check if the path is viable
-    let path = match path_split.next() {
-        Some(path) => path.to_string(),
-        None => {
-            println!("http header line does not seem to be correct");
-            String::from("")
+        if self.http_only {
+            value.push_str("; HttpOnly");
         }
-    };
 
-    //create new option for the additional information in the path
-    //option in case there is not additional information
-    let query_string: Option<String> = None;
+        if self.secure {
+            value.push_str("; Secure");
+        }
 
-    if let Method::GET = method {
-        //put everthing after the '?' into an option
-        //so its possible to differentiate 
-        //that there is not get string
-        let _query_string = match path_split.next() {
-            Some(string) => Some(convert_query_string(string.to_string())),
-            None => None,
-        };
+        match self.same_site {
+            Some(SameSite::Strict) => value.push_str("; SameSite=Strict"),
+            Some(SameSite::Lax) => value.push_str("; SameSite=Lax"),
+            //browsers only accept SameSite=None for secure cookies
+            Some(SameSite::None) => value.push_str("; SameSite=None"),
+            None => {},
+        }
 
+        value
     }
+}
 
-    //put the protocol version into a variable
-    let protocol = request_line_split_iter.next().unwrap().to_string();
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StatusCode {
+    Ok,
+    Created,
+    NoContent,
+    PartialContent,
+    MovedPermanently,
+    Found,
+    NotModified,
+    BadRequest,
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    MethodNotAllowed,
+    RequestTimeout,
+    LengthRequired,
+    PayloadTooLarge,
+    RangeNotSatisfiable,
+    TooManyRequests,
+    InternalServerError,
+    NotImplemented,
+    ServiceUnavailable,
+}
 
-    let request_line_struc = RequestLine {
-        empty: false,
-        method: method,
-        path: path,
-        query_string: query_string,
-        protocol: protocol,
-    };
+impl StatusCode {
+    pub fn code (&self) -> u16 {
+        match self {
+            StatusCode::Ok => 200,
+            StatusCode::Created => 201,
+            StatusCode::NoContent => 204,
+            StatusCode::PartialContent => 206,
+            StatusCode::MovedPermanently => 301,
+            StatusCode::Found => 302,
+            StatusCode::NotModified => 304,
+            StatusCode::BadRequest => 400,
+            StatusCode::Unauthorized => 401,
+            StatusCode::Forbidden => 403,
+            StatusCode::NotFound => 404,
+            StatusCode::MethodNotAllowed => 405,
+            StatusCode::RequestTimeout => 408,
+            StatusCode::LengthRequired => 411,
+            StatusCode::PayloadTooLarge => 413,
+            StatusCode::RangeNotSatisfiable => 416,
+            StatusCode::TooManyRequests => 429,
+            StatusCode::InternalServerError => 500,
+            StatusCode::NotImplemented => 501,
+            StatusCode::ServiceUnavailable => 503,
+        }
+    }
 
-    request_line_struc 
+    pub fn reason (&self) -> &'static str {
+        match self {
+            StatusCode::Ok => "OK",
+            StatusCode::Created => "Created",
+            StatusCode::NoContent => "No Content",
+            StatusCode::PartialContent => "Partial Content",
+            StatusCode::MovedPermanently => "Moved Permanently",
+            StatusCode::Found => "Found",
+            StatusCode::NotModified => "Not Modified",
+            StatusCode::BadRequest => "Bad Request",
+            StatusCode::Unauthorized => "Unauthorized",
+            StatusCode::Forbidden => "Forbidden",
+            StatusCode::NotFound => "Not Found",
+            StatusCode::MethodNotAllowed => "Method Not Allowed",
+            StatusCode::RequestTimeout => "Request Timeout",
+            StatusCode::LengthRequired => "Length Required",
+            StatusCode::PayloadTooLarge => "Payload Too Large",
+            StatusCode::RangeNotSatisfiable => "Range Not Satisfiable",
+            StatusCode::TooManyRequests => "Too Many Requests",
+            StatusCode::InternalServerError => "Internal Server Error",
+            StatusCode::NotImplemented => "Not Implemented",
+            StatusCode::ServiceUnavailable => "Service Unavailable",
+        }
+    }
 
+    //responses with these status codes never have a body
+    fn has_body (&self) -> bool {
+        !matches!(self, StatusCode::NoContent | StatusCode::NotModified)
+    }
 }
 
-pub fn parse_header_accept (head_string: &str) -> HashMap<String, String> {
-    //create empty hash map 
-    let mut media_types = HashMap::new();
+//http headers, names are compared case insensitive
+//the order of the headers is kept and a name can appear multiple times, for example Set-Cookie
+#[derive(Debug, Default, Clone)]
+pub struct HeaderMap {
+    headers: Vec<(String, String)>,
+}
 
-    //split the string by ','
-    let head_string_split = head_string.split(',');
+impl HeaderMap {
+    pub fn new () -> HeaderMap {
+        HeaderMap { headers: Vec::new() }
+    }
 
-    //loop through the individual types
-    for media_type in head_string_split {
+    //set the header, replacing all headers with the same name
+    pub fn insert (&mut self, name: &str, value: &str) {
+        self.remove(name);
+        self.append(name, value);
+    }
 
-        //seperate the quality value
-        let mut media_type_preference = media_type.split(';');
+    //add the header, keeping headers with the same name
+    pub fn append (&mut self, name: &str, value: &str) {
+        self.headers.push((name.to_string(), value.to_string()));
+    }
 
-        //get the name of the media type
-        let media_type = match media_type_preference.next() {
-            Some(mtype) => mtype.to_string(),
-            None => String::from(""),
+    //first value of the header
+    pub fn get (&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header_name, _)| header_name.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    //all values of the header in the order they were added
+    pub fn get_all (&self, name: &str) -> Vec<&str> {
+        self.headers
+            .iter()
+            .filter(|(header_name, _)| header_name.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+            .collect()
+    }
+
+    pub fn contains_key (&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    pub fn remove (&mut self, name: &str) {
+        self.headers.retain(|(header_name, _)| !header_name.eq_ignore_ascii_case(name));
+    }
+
+    pub fn iter (&self) -> impl Iterator<Item = (&str, &str)> {
+        self.headers.iter().map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+}
+
+pub enum ResponseBody {
+    Empty,
+    Bytes(Vec<u8>),
+    //file with its length, copied to the stream from the current position while sending
+    File(File, u64),
+    //body with known length, for example parts of files
+    Reader(Box<dyn Read + Send>, u64),
+    //body with unknown length, sent in chunks
+    Stream(Box<dyn Read + Send>),
+}
+
+pub struct HTTPResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: ResponseBody,
+}
+
+impl HTTPResponse {
+    pub fn new (status: StatusCode) -> HTTPResponse {
+        HTTPResponse {
+            status,
+            headers: HeaderMap::new(),
+            body: ResponseBody::Empty,
+        }
+    }
+
+    pub fn header (mut self, name: &str, value: &str) -> HTTPResponse {
+        self.headers.insert(name, value);
+        self
+    }
+
+    //every cookie is sent in its own Set-Cookie header
+    pub fn cookie (mut self, cookie: &Cookie) -> HTTPResponse {
+        self.headers.append("Set-Cookie", &cookie.header_value());
+        self
+    }
+
+    pub fn body (mut self, body: Vec<u8>) -> HTTPResponse {
+        self.body = ResponseBody::Bytes(body);
+        self
+    }
+
+    pub fn body_file (mut self, file: File, length: u64) -> HTTPResponse {
+        self.body = ResponseBody::File(file, length);
+        self
+    }
+
+    pub fn body_reader (mut self, reader: Box<dyn Read + Send>, length: u64) -> HTTPResponse {
+        self.body = ResponseBody::Reader(reader, length);
+        self
+    }
+
+    pub fn body_stream (mut self, stream: Box<dyn Read + Send>) -> HTTPResponse {
+        self.body = ResponseBody::Stream(stream);
+        self
+    }
+
+    //write status line and headers
+    //date, server and connection headers are added, if they are not set
+    fn write_head (&mut self, request: &mut HTTPRequest) -> std::io::Result<()> {
+
+        let mut head = format!("HTTP/1.1 {} {}\r\n", self.status.code(), self.status.reason());
+
+        if !self.headers.contains_key("Date") {
+            self.headers.insert("Date", &http_date(SystemTime::now()));
+        }
+
+        if !self.headers.contains_key("Server") {
+            self.headers.insert("Server", SERVER_NAME);
+        }
+
+        if !self.headers.contains_key("Connection") {
+            self.headers.insert("Connection", request.connection_header());
+        }
+
+        for (name, value) in self.headers.iter() {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+
+        head.push_str("\r\n");
+
+        request.stream.write_all(head.as_bytes())
+    }
+
+    //serialize the response and write it to the stream of the request
+    pub fn send (mut self, request: &mut HTTPRequest) -> std::io::Result<()> {
+
+        //the length of the body is announced, so the connection can be used for further requests
+        let content_length = match &self.body {
+            ResponseBody::Empty => Some(0),
+            ResponseBody::Bytes(bytes) => Some(bytes.len() as u64),
+            ResponseBody::File(_, length) => Some(*length),
+            ResponseBody::Reader(_, length) => Some(*length),
+            ResponseBody::Stream(_) => None,
         };
-        
-        //check if there is a preference/quality value for the media type
-        let preference = match media_type_preference.next() {
-            //value looks something like 'q=0.8'
-            //if there is no preference availalbe, 1.0 is the default
-            Some(preference) => {
-                match preference.split('=').nth(1) {
-                    Some(qvalue) => qvalue.to_string(),
-                    None => String::from(""),
+
+        let content_length = match content_length {
+            Some(length) => length,
+            None => {
+                //the body is sent in chunks
+                let body = std::mem::replace(&mut self.body, ResponseBody::Empty);
+                let mut writer = self.send_chunked(request)?;
+
+                if let ResponseBody::Stream(mut stream) = body {
+                    std::io::copy(&mut stream, &mut writer)?;
                 }
-            }, 
-            None => String::from("1.0"),
+
+                return writer.finish();
+            },
         };
-        
-        //insert into hash map
-        media_types.insert(media_type, preference);
 
-        
+        if self.status.has_body() && !self.headers.contains_key("Content-Length") {
+            self.headers.insert("Content-Length", &content_length.to_string());
+        }
+
+        self.write_head(request)?;
+
+        if !self.status.has_body() {
+            return Ok(());
+        }
+
+        match &mut self.body {
+            ResponseBody::Empty => {},
+            ResponseBody::Bytes(bytes) => request.stream.write_all(bytes)?,
+            ResponseBody::File(file, length) => send_file(file, *length, &mut request.stream)?,
+            ResponseBody::Reader(reader, length) => {
+                std::io::copy(&mut reader.take(*length), &mut request.stream)?;
+            },
+            ResponseBody::Stream(_) => {},
+        }
+
+        request.stream.flush()
+    }
+
+    //send status line and headers, the body is written in chunks to the returned writer afterwards
+    //used for bodies which are too large to buffer or are generated while sending
+    pub fn send_chunked (mut self, request: &mut HTTPRequest) -> std::io::Result<ChunkedWriter<'_>> {
+
+        //http/1.0 does not know chunks, the end of the body is marked by closing the connection
+        let chunked = request.request_line.protocol == "HTTP/1.1";
+
+        self.headers.remove("Content-Length");
+
+        if chunked {
+            self.headers.insert("Transfer-Encoding", "chunked");
+        } else {
+            self.headers.insert("Connection", "close");
+        }
+
+        self.write_head(request)?;
+
+        Ok(ChunkedWriter { stream: &mut request.stream, chunked })
+    }
+}
+
+//writer for a response body with transfer encoding chunked
+//every write is sent as one chunk, finish sends the last chunk
+pub struct ChunkedWriter<'a> {
+    stream: &'a mut TcpStream,
+    chunked: bool,
+}
+
+impl Write for ChunkedWriter<'_> {
+    fn write (&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        //an empty chunk would end the body
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        if self.chunked {
+            //chunk size in hex, followed by the data and a line break
+            write!(self.stream, "{:x}\r\n", buf.len())?;
+            self.stream.write_all(buf)?;
+            self.stream.write_all(b"\r\n")?;
+        } else {
+            self.stream.write_all(buf)?;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush (&mut self) -> std::io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl ChunkedWriter<'_> {
+    //end the body, has to be called after the last write
+    pub fn finish (self) -> std::io::Result<()> {
+        if self.chunked {
+            //last chunk with size 0 and an empty trailer
+            self.stream.write_all(b"0\r\n\r\n")?;
+            self.stream.flush()
+        } else {
+            self.stream.flush()?;
+            self.stream.shutdown(Shutdown::Write)
+        }
+    }
+}
+
+//size of the pieces in which files are copied to the connection
+const FILE_CHUNK_SIZE: usize = 64 * 1024;
+
+//copy length bytes from the current position of the file to the connection
+//the memory used does not depend on the size of the file
+fn send_file (file: &mut File, length: u64, stream: &mut TcpStream) -> std::io::Result<()> {
+    //the connection is not encrypted, so the kernel can copy the file directly
+    #[cfg(target_os = "linux")]
+    if send_file_linux(file, length, stream)? {
+        return Ok(());
+    }
+
+    send_file_chunks(file, length, stream)
+}
+
+//copy the file with sendfile(2), without copying it through user space
+//returns false, if sendfile can not be used for the file and nothing has been sent
+#[cfg(target_os = "linux")]
+fn send_file_linux (file: &mut File, length: u64, stream: &mut TcpStream) -> std::io::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut offset = file.stream_position()? as libc::off_t;
+    let mut remaining = length;
+
+    while remaining > 0 {
+        //linux sends at most 0x7ffff000 bytes per call
+        let count = remaining.min(0x7fff_f000) as usize;
+
+        //the descriptors are valid while file and stream are borrowed
+        //sendfile moves offset forward by the sent bytes
+        let sent = unsafe { libc::sendfile(stream.as_raw_fd(), file.as_raw_fd(), &mut offset, count) };
+
+        if sent < 0 {
+            let err = std::io::Error::last_os_error();
+
+            if err.kind() == ErrorKind::Interrupted {
+                continue;
+            }
+
+            //file systems, which do not support sendfile
+            if remaining == length && matches!(err.raw_os_error(), Some(libc::EINVAL) | Some(libc::ENOSYS)) {
+                return Ok(false);
+            }
+
+            return Err(err);
+        }
+
+        if sent == 0 {
+            return Err(std::io::Error::new(ErrorKind::UnexpectedEof, "file is shorter than announced"));
+        }
+
+        remaining -= sent as u64;
+    }
+
+    //sendfile does not move the position of the file
+    file.seek(SeekFrom::Start(offset as u64))?;
+
+    Ok(true)
+}
+
+//copy the file in fixed size chunks, works on every platform
+fn send_file_chunks (file: &mut File, length: u64, stream: &mut TcpStream) -> std::io::Result<()> {
+    let mut buffer = vec![0; FILE_CHUNK_SIZE.min(length as usize)];
+    let mut remaining = length;
+
+    while remaining > 0 {
+        let max = buffer.len().min(remaining as usize);
+        let read = file.read(&mut buffer[..max])?;
+
+        if read == 0 {
+            return Err(std::io::Error::new(ErrorKind::UnexpectedEof, "file is shorter than announced"));
+        }
+
+        stream.write_all(&buffer[..read])?;
+        remaining -= read as u64;
+    }
+
+    Ok(())
+}
+
+const SERVER_NAME: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+//format a time as http date, for example 'Sun, 06 Nov 1994 08:49:37 GMT'
+pub fn http_date (time: SystemTime) -> String {
+    let seconds = time.duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0);
+
+    let days = (seconds / 86400) as i64;
+    let seconds_of_day = seconds % 86400;
+
+    //1970-01-01 was a thursday
+    let weekday = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"][(days % 7) as usize];
+
+    //convert days since 1970 into year, month and day
+    //http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = days + 719468;
+    let era = days / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    let month_name = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"
+    ][month as usize - 1];
+
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        weekday, day, month_name, year,
+        seconds_of_day / 3600, seconds_of_day % 3600 / 60, seconds_of_day % 60
+    )
+}
+
+//parse a date in the format of http_date, 'Sun, 06 Nov 1994 08:49:37 GMT'
+//the obsolete formats are not supported, invalid dates return None
+pub fn parse_http_date (date: &str) -> Option<SystemTime> {
+    let (_weekday, rest) = date.trim().split_once(", ")?;
+    let parts: Vec<&str> = rest.split(' ').collect();
+
+    if parts.len() != 5 || parts[4] != "GMT" {
+        return None;
+    }
+
+    let day: i64 = parts[0].parse().ok()?;
+    let month = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"
+    ].iter().position(|name| *name == parts[1])? as i64 + 1;
+    let year: i64 = parts[2].parse().ok()?;
+
+    let time: Vec<u64> = parts[3].split(':').map(|part| part.parse().ok()).collect::<Option<Vec<u64>>>()?;
+    if time.len() != 3 || time[0] > 23 || time[1] > 59 || time[2] > 60 || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    //convert year, month and day into days since 1970
+    //http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+
+    if days < 0 {
+        return None;
+    }
+
+    let seconds = days as u64 * 86400 + time[0] * 3600 + time[1] * 60 + time[2];
+
+    Some(UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+pub struct RequestLine {
+    pub empty: bool,
+    pub method: Method,
+    pub path: String,
+    pub query_string: Option<QueryString>,
+    pub protocol: String,
+}
+
+impl RequestLine {
+    pub fn empty () -> RequestLine {
+
+        RequestLine {
+            empty: true,
+            method: Method::Undefined,
+            path: String::new(),
+            query_string: None,
+            protocol: String::new(),
+        }
+
+    }
+}
+
+pub fn read_request_line (buf_reader: &mut BufReader<&TcpStream>) -> String {
+
+    //create new string for the request line
+    let mut request_line = String::new();
+
+    //read the line into the new string
+    //hint: connection reset by peer
+    match buf_reader.read_line(&mut request_line) {
+        Ok(_) => {},
+        Err(err) => println!("error: {}", err), 
+    }
+
+
+    let request_line = request_line.trim().to_string();
+
+    request_line
+}
+
+pub fn parse_request_line (request_line: String) -> RequestLine {
+
+    //if the stream does not send a correct request line, then stop handling the connetion
+    if request_line.is_empty() {
+        println!("---------request line was empty");
+        return RequestLine::empty();
+    }
+
+    //split status line by space
+    //a request line needs method, path and protocol, otherwise the connection is closed
+    let parts: Vec<&str> = request_line.split_whitespace().collect();
+    let (method, path, protocol) = match parts[..] {
+        [method, path, protocol] => (method, path, protocol),
+        _ => {
+            println!("---------invalid request line: {}", request_line);
+            return RequestLine::empty();
+        },
+    };
+
+    //extract the method from the http request
+    let method = match method {
+            "GET" => Method::GET,
+            "POST" => Method::POST,
+            "PUT" => Method::PUT,
+            "DELETE" => Method::DELETE,
+            "PATCH" => Method::PATCH,
+            "HEAD" => Method::HEAD,
+            "OPTIONS" => Method::OPTIONS,
+            _ => Method::Undefined,
+    };
+
+    //split the path at the first '?' to extract any information from the path
+    let (path, query) = match path.split_once('?') {
+        Some((path, query)) => (path.to_string(), Some(query)),
+        None => (path.to_string(), None),
+    };
+
+    //create new option for the additional information in the path
+    //option in case there is not additional information
+    let mut query_string: Option<QueryString> = None;
+
+    if let Method::GET = method {
+        //put everthing after the '?' into an option
+        //so its possible to differentiate 
+        //that there is not get string
+        query_string = query.map(convert_query_string);
+
+    }
+
+    //put the protocol version into a variable
+    let protocol = protocol.to_string();
+
+    RequestLine {
+        empty: false,
+        method,
+        path,
+        query_string,
+        protocol,
+    } 
+
+}
+
+pub fn parse_header_accept (head_string: &str) -> HashMap<String, String> {
+    //create empty hash map 
+    let mut media_types = HashMap::new();
+
+    //split the string by ','
+    let head_string_split = head_string.split(',');
+
+    //loop through the individual types
+    for media_type in head_string_split {
+
+        //seperate the quality value
+        let mut media_type_preference = media_type.split(';');
+
+        //get the name of the media type
+        let media_type = match media_type_preference.next() {
+            Some(mtype) => mtype.to_string(),
+            None => String::from(""),
+        };
+        
+        //check if there is a preference/quality value for the media type
+        let preference = match media_type_preference.next() {
+            //value looks something like 'q=0.8'
+            //if there is no preference availalbe, 1.0 is the default
+            Some(preference) => {
+                match preference.split('=').nth(1) {
+                    Some(qvalue) => qvalue.to_string(),
+                    None => String::from(""),
+                }
+            }, 
+            None => String::from("1.0"),
+        };
+        
+        //insert into hash map
+        media_types.insert(media_type, preference);
+
+        
+    }
+
+    //return the media types
+    media_types
+    
+
+}
+
+pub fn read_http_headers (buf_reader: &mut BufReader<&TcpStream>) -> HeaderMap {
+
+    //creat new header map holding the headers
+    let mut headers = HeaderMap::new();
+
+    //loop through the lines of the header
+    //and extract the individual paramteres
+    loop {
+
+        //create new empty string for the header line
+        let mut header_line = String::new();
+
+        //read new line from the stream
+        //stop reading, if the connection has been closed or timed out
+        if let Err(err) = buf_reader.read_line(&mut header_line) {
+            println!("error: {}", err);
+            break;
+        }
+
+        //trim empty spaces from the line
+        let header_line = header_line.trim();
+
+        // if the line is empty, break from the loop
+        // no more lines will be read
+        if header_line.is_empty() {
+            break;
+        }
+
+        //split the line at the first ':' to get the description and value of the parameter
+        //the value can contain ':' as well, for example 'Host: 127.0.0.1:7878'
+        let (header_description, header_value) = match header_line.split_once(':') {
+            Some((description, value)) => (description.trim(), value.trim()),
+            None => {
+                println!("invalid header line: {}", header_line);
+                continue;
+            },
+        };
+
+        //add the pair to the headers
+        headers.append(header_description, header_value);
+
+    }
+
+
+    //return the headers from the function
+    headers
+}
+
+//check if the client wants to keep the connection open after the response
+//http/1.1 keeps the connection open, unless the client sends 'Connection: close'
+//http/1.0 closes the connection, unless the client sends 'Connection: keep-alive'
+pub fn connection_keep_alive (protocol: &str, headers: &HeaderMap) -> bool {
+    //the connection header can hold multiple options seperated by ','
+    let connection_option = |option: &str| {
+        match headers.get("Connection") {
+            Some(connection) => connection
+                .split(',')
+                .any(|value| value.trim().eq_ignore_ascii_case(option)),
+            None => false,
+        }
+    };
+
+    match protocol {
+        "HTTP/1.1" => !connection_option("close"),
+        _ => connection_option("keep-alive"),
+    }
+}
+
+pub fn read_http_body (
+    buf_reader: &mut BufReader<&TcpStream>, 
+    content_length: usize
+) -> std::io::Result<String> {
+
+    //create empty vector with the length of the content
+    let mut body: Vec<u8> = vec![0; content_length];
+
+    //read content into vector
+    buf_reader.read_exact(&mut body)?; 
+
+    //turn body from bytes into a string
+    String::from_utf8(body).map_err(|err| std::io::Error::new(ErrorKind::InvalidData, err))
+}
+
+//check if the body of the request is sent in chunks
+//chunked has to be the last transfer coding
+pub fn transfer_encoding_chunked (headers: &HeaderMap) -> bool {
+    match headers.get("Transfer-Encoding") {
+        Some(encoding) => encoding
+            .split(',')
+            .next_back()
+            .is_some_and(|coding| coding.trim().eq_ignore_ascii_case("chunked")),
+        None => false,
+    }
+}
+
+//longest line with a chunk size, including chunk extensions
+const CHUNK_SIZE_LINE_MAX: usize = 1024;
+//largest trailer after the last chunk
+const TRAILER_MAX_SIZE: usize = 8 * 1024;
+
+//read a line, which is not longer than the limit
+//returns an empty string, if the connection has been closed
+fn read_line_limited (buf_reader: &mut BufReader<&TcpStream>, limit: usize) -> std::io::Result<String> {
+    let mut line: Vec<u8> = Vec::new();
+    let read = buf_reader.by_ref().take(limit as u64).read_until(b'\n', &mut line)?;
+
+    if read == limit && !line.ends_with(b"\n") {
+        return Err(std::io::Error::new(ErrorKind::InvalidData, "line too long"));
+    }
+
+    String::from_utf8(line).map_err(|err| std::io::Error::new(ErrorKind::InvalidData, err))
+}
+
+//read a body with transfer encoding chunked
+//every chunk starts with its size in hex, the body ends with a chunk of size 0
+//fields sent as trailer after the last chunk are stored in the trailers, not in the headers
+pub fn read_http_body_chunked (
+    buf_reader: &mut BufReader<&TcpStream>, 
+    trailers: &mut HeaderMap,
+    max_size: usize
+) -> std::io::Result<String> {
+
+    let invalid_data = |message: &str| std::io::Error::new(ErrorKind::InvalidData, message.to_string());
+
+    let mut body: Vec<u8> = Vec::new();
+
+    loop {
+        //read the line with the chunk size
+        let size_line = read_line_limited(buf_reader, CHUNK_SIZE_LINE_MAX)?;
+        if size_line.is_empty() {
+            return Err(invalid_data("connection closed before last chunk"));
+        }
+
+        //chunk extensions after ';' are ignored
+        let size = size_line.split(';').next().unwrap_or("").trim();
+        let size = usize::from_str_radix(size, 16).map_err(|_| invalid_data("invalid chunk size"))?;
+
+        //last chunk
+        if size == 0 {
+            break;
+        }
+
+        //stop reading, if the client sends more than allowed
+        //compared without adding, a huge chunk size would overflow
+        if size > max_size - body.len() {
+            return Err(invalid_data("body too large"));
+        }
+
+        //read the chunk without allocating the announced size up front
+        let read = buf_reader.by_ref().take(size as u64).read_to_end(&mut body)?;
+        if read < size {
+            return Err(invalid_data("connection closed inside chunk"));
+        }
+
+        //every chunk ends with a line break
+        let line_end = read_line_limited(buf_reader, 2)?;
+        if line_end != "\r\n" && line_end != "\n" {
+            return Err(invalid_data("missing line break after chunk"));
+        }
+    }
+
+    //read the trailer, it has the same format as the headers and ends with an empty line
+    //the trailer is limited in size like the body
+    let mut trailer_size = 0;
+    loop {
+        let line = read_line_limited(buf_reader, TRAILER_MAX_SIZE - trailer_size)
+            .map_err(|_| invalid_data("trailer too large"))?;
+        trailer_size += line.len();
+
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+
+        match line.split_once(':') {
+            Some((name, value)) => trailers.append(name.trim(), value.trim()),
+            None => println!("invalid trailer line: {}", line),
+        }
+    }
+
+    //turn body from bytes into a string
+    String::from_utf8(body).map_err(|err| std::io::Error::new(ErrorKind::InvalidData, err))
+}
+
+//how symbolic links below the root directory are handled
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SymlinkPolicy {
+    //files behind a symbolic link are not served
+    Deny,
+    //links are followed, if the target is inside the root directory
+    FollowWithinRoot,
+    //links are followed everywhere
+    Follow,
+}
+
+pub struct StaticFileOptions {
+    pub symlinks: SymlinkPolicy,
+    //files and directories, whose name starts with a dot
+    pub allow_dotfiles: bool,
+}
+
+pub const STATIC_FILE_OPTIONS: StaticFileOptions = StaticFileOptions {
+    symlinks: SymlinkPolicy::FollowWithinRoot,
+    allow_dotfiles: false,
+};
+
+//turn the path of a request into the path of a file inside the root directory
+//returns the status for the response, if the path is not allowed or the file does not exist
+pub fn resolve_static_path (
+    root: &str, 
+    request_path: &str, 
+    options: &StaticFileOptions
+) -> Result<PathBuf, StatusCode> {
+
+    //percent encoded dots and slashes are decoded before the path is checked
+    let decoded = String::from_utf8(percent_decode(request_path)).map_err(|_| StatusCode::BadRequest)?;
+
+    if decoded.contains('\0') {
+        return Err(StatusCode::BadRequest);
+    }
+
+    //resolve '.' and '..' segments, '..' must not go above the root
+    let mut segments: Vec<&str> = Vec::new();
+
+    for segment in decoded.split('/') {
+        match segment {
+            "" | "." => {},
+            ".." => {
+                if segments.pop().is_none() {
+                    return Err(StatusCode::BadRequest);
+                }
+            },
+            _ => segments.push(segment),
+        }
+    }
+
+    //hidden files are answered as if they would not exist
+    if !options.allow_dotfiles && segments.iter().any(|segment| segment.starts_with('.')) {
+        return Err(StatusCode::NotFound);
+    }
+
+    if segments.is_empty() {
+        segments.push("login.html");
+    } else if decoded.ends_with('/') {
+        //a path with a trailing '/' names a directory, directories are not served
+        return Err(StatusCode::NotFound);
+    }
+
+    let root = Path::new(root);
+    let mut path = root.to_path_buf();
+
+    for segment in &segments {
+        path.push(segment);
+
+        //check every part of the path, a link can be a directory as well
+        if options.symlinks == SymlinkPolicy::Deny {
+            match std::fs::symlink_metadata(&path) {
+                Ok(metadata) if metadata.file_type().is_symlink() => return Err(StatusCode::Forbidden),
+                Ok(_) => {},
+                Err(_) => return Err(StatusCode::NotFound),
+            }
+        }
+    }
+
+    //resolve links, the canonical path has to be inside the canonical root
+    let canonical_root = root.canonicalize().map_err(|_| StatusCode::NotFound)?;
+    let canonical_path = path.canonicalize().map_err(|err| match err.kind() {
+        ErrorKind::NotFound => StatusCode::NotFound,
+        ErrorKind::PermissionDenied => StatusCode::Forbidden,
+        _ => StatusCode::InternalServerError,
+    })?;
+
+    if options.symlinks != SymlinkPolicy::Follow && !canonical_path.starts_with(&canonical_root) {
+        return Err(StatusCode::Forbidden);
+    }
+
+    //only files are served
+    if !canonical_path.is_file() {
+        return Err(StatusCode::NotFound);
+    }
+
+    Ok(canonical_path)
+}
+
+//file with the Cache-Control header of static files per path prefix
+//every line has the format '/prefix/ value', '#' starts a comment
+//the first rule with a matching path prefix is used
+pub const CACHE_CONTROL_CONFIG_FILE: &str = "cache-control.conf";
+
+//used, if the config file is missing or empty
+//files in /assets/ have the hash of their content in the name, so they never change
+pub const DEFAULT_CACHE_CONTROL_RULES: &[(&str, &str)] = &[
+    ("/assets/", "public, max-age=31536000, immutable"),
+    ("/", "no-cache"),
+];
+
+//read the rules from the config file, in the order of the file
+pub fn load_cache_control_rules (path: &str) -> Vec<(String, String)> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) => {
+            if err.kind() != ErrorKind::NotFound {
+                println!("could not read cache control config {}: {}", path, err);
+            }
+            String::new()
+        },
+    };
+
+    let mut rules = Vec::new();
+
+    for line in content.lines() {
+        let line = line.split('#').next().unwrap_or("").trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        //the value can contain spaces, for example 'public, max-age=60'
+        match line.split_once(char::is_whitespace) {
+            Some((prefix, cache_control)) => rules.push((prefix.to_string(), cache_control.trim().to_string())),
+            None => println!("invalid cache control rule: {}", line),
+        }
+    }
+
+    if rules.is_empty() {
+        return DEFAULT_CACHE_CONTROL_RULES
+            .iter()
+            .map(|(prefix, cache_control)| (prefix.to_string(), cache_control.to_string()))
+            .collect();
+    }
+
+    rules
+}
+
+static CACHE_CONTROL_RULES: LazyLock<Vec<(String, String)>> = LazyLock::new(|| load_cache_control_rules(CACHE_CONTROL_CONFIG_FILE));
+
+pub fn cache_control_for_path (path: &str) -> Option<&'static str> {
+    CACHE_CONTROL_RULES
+        .iter()
+        .find(|(prefix, _)| path.starts_with(prefix.as_str()))
+        .map(|(_, cache_control)| cache_control.as_str())
+}
+
+//entity tag of a file from its size and modification time
+//the file is not read, so a change, which keeps size and time, is not detected
+pub fn file_etag (metadata: &std::fs::Metadata) -> String {
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default();
+
+    format!("\"{:x}-{:x}-{:x}\"", metadata.len(), modified.as_secs(), modified.subsec_nanos())
+}
+
+//compare two entity tags, weak tags are equal to strong tags with the same value
+fn etag_weak_eq (a: &str, b: &str) -> bool {
+    a.trim_start_matches("W/") == b.trim_start_matches("W/")
+}
+
+//check the conditional headers of a request, true if the client has the current version of the file
+pub fn not_modified (request: &HTTPRequest, etag: &str, last_modified: Option<SystemTime>) -> bool {
+    //If-Modified-Since is ignored, if If-None-Match is sent
+    if let Some(if_none_match) = request.headers.get("If-None-Match") {
+        return if_none_match
+            .split(',')
+            .map(|tag| tag.trim())
+            .any(|tag| tag == "*" || etag_weak_eq(tag, etag));
+    }
+
+    let if_modified_since = match request.headers.get("If-Modified-Since").and_then(parse_http_date) {
+        Some(if_modified_since) => if_modified_since,
+        None => return false,
+    };
+
+    //http dates have no fractions of seconds
+    match last_modified.and_then(|modified| modified.duration_since(UNIX_EPOCH).ok()) {
+        Some(modified) => UNIX_EPOCH + Duration::from_secs(modified.as_secs()) <= if_modified_since,
+        None => false,
+    }
+}
+
+//result of parsing the Range header
+#[derive(Debug, PartialEq)]
+pub enum ByteRanges {
+    //the header is invalid or not supported, the whole file is sent
+    Ignore,
+    //first and last byte of each range, both included
+    Satisfiable(Vec<(u64, u64)>),
+    //no range overlaps the file
+    Unsatisfiable,
+}
+
+//more ranges are not answered, many small ranges cost more than the whole file
+const MAX_RANGES: usize = 16;
+
+//parse 'bytes=0-99, 200-, -50' for a file of the length
+pub fn parse_range_header (header: &str, length: u64) -> ByteRanges {
+    let spec = match header.trim().strip_prefix("bytes=") {
+        Some(spec) => spec,
+        None => return ByteRanges::Ignore,
+    };
+
+    let parts: Vec<&str> = spec.split(',').map(|part| part.trim()).filter(|part| !part.is_empty()).collect();
+
+    if parts.is_empty() || parts.len() > MAX_RANGES {
+        return ByteRanges::Ignore;
+    }
+
+    let mut ranges = Vec::new();
+
+    for part in parts {
+        let (start, end) = match part.split_once('-') {
+            Some((start, end)) => (start.trim(), end.trim()),
+            None => return ByteRanges::Ignore,
+        };
+
+        if start.is_empty() {
+            //'-50' are the last 50 bytes
+            let suffix: u64 = match end.parse() {
+                Ok(suffix) => suffix,
+                Err(_) => return ByteRanges::Ignore,
+            };
+
+            if suffix > 0 && length > 0 {
+                ranges.push((length.saturating_sub(suffix), length - 1));
+            }
+            continue;
+        }
+
+        let start: u64 = match start.parse() {
+            Ok(start) => start,
+            Err(_) => return ByteRanges::Ignore,
+        };
+
+        //'200-' is everything from byte 200
+        let end: Option<u64> = match end {
+            "" => None,
+            _ => match end.parse() {
+                Ok(end) => Some(end),
+                Err(_) => return ByteRanges::Ignore,
+            },
+        };
+
+        if end.is_some_and(|end| end < start) {
+            return ByteRanges::Ignore;
+        }
+
+        //ranges, which start after the end of the file, are left out
+        if start < length {
+            ranges.push((start, end.unwrap_or(length - 1).min(length - 1)));
+        }
+    }
+
+    if ranges.is_empty() {
+        return ByteRanges::Unsatisfiable;
+    }
+
+    ByteRanges::Satisfiable(ranges)
+}
+
+//check If-Range, a range is only sent if the client has the current version of the file
+//otherwise the whole file is sent
+pub fn if_range_matches (request: &HTTPRequest, etag: &str, last_modified: Option<SystemTime>) -> bool {
+    let if_range = match request.headers.get("If-Range") {
+        Some(if_range) => if_range.trim(),
+        None => return true,
+    };
+
+    //entity tags have to match strong, weak tags never match
+    if if_range.starts_with('"') {
+        return if_range == etag;
+    }
+
+    let last_modified = last_modified
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|modified| UNIX_EPOCH + Duration::from_secs(modified.as_secs()));
+
+    match (parse_http_date(if_range), last_modified) {
+        (Some(date), Some(last_modified)) => date == last_modified,
+        _ => false,
+    }
+}
+
+//part of a file, the file is moved to the start of the part, when it is read the first time
+struct FileRange {
+    file: File,
+    start: u64,
+    remaining: u64,
+    positioned: bool,
+}
+
+impl Read for FileRange {
+    fn read (&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if !self.positioned {
+            self.file.seek(SeekFrom::Start(self.start))?;
+            self.positioned = true;
+        }
+
+        if self.remaining == 0 {
+            return Ok(0);
+        }
+
+        let max = buf.len().min(self.remaining as usize);
+        let read = self.file.read(&mut buf[..max])?;
+        self.remaining -= read as u64;
+
+        Ok(read)
+    }
+}
+
+//body of type multipart/byteranges for multiple ranges of a file
+//returns the boundary, the body and its length
+fn multipart_byteranges (
+    file: &File, 
+    ranges: &[(u64, u64)], 
+    file_length: u64, 
+    content_type: &str
+) -> std::io::Result<(String, Box<dyn Read + Send>, u64)> {
+
+    let boundary = format!("{:016x}{:016x}", rand::random::<u64>(), rand::random::<u64>());
+
+    let mut body: Box<dyn Read + Send> = Box::new(std::io::empty());
+    let mut length = 0;
+
+    for (start, end) in ranges {
+        let head = format!(
+            "\r\n--{}\r\nContent-Type: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+            boundary, content_type, start, end, file_length
+        );
+
+        length += head.len() as u64 + (end - start + 1);
+
+        //every part reads the same file, the parts are read one after another
+        let range = FileRange { file: file.try_clone()?, start: *start, remaining: end - start + 1, positioned: false };
+
+        body = Box::new(body.chain(std::io::Cursor::new(head.into_bytes())).chain(range));
+    }
+
+    let closing = format!("\r\n--{}--\r\n", boundary);
+    length += closing.len() as u64;
+
+    body = Box::new(body.chain(std::io::Cursor::new(closing.into_bytes())));
+
+    Ok((boundary, body, length))
+}
+
+//read the start of the file to recognize the type, the file is moved back to the start afterwards
+fn read_file_start (file: &mut File) -> std::io::Result<Vec<u8>> {
+    let mut start = Vec::with_capacity(512);
+    Read::by_ref(file).take(512).read_to_end(&mut start)?;
+    file.seek(SeekFrom::Start(0))?;
+
+    Ok(start)
+}
+
+pub fn serve_static_file (
+    request: &HTTPRequest, 
+    _database_connections: Arc<DatabaseConnectionPool>
+) -> HTTPResponse {
+
+    //create full path inside the root directory
+    let path = match resolve_static_path(constants::ROOT, &request.request_line.path, &STATIC_FILE_OPTIONS) {
+        Ok(path) => path,
+        Err(StatusCode::NotFound) => return response_404(),
+        Err(status) => return status_response(status),
+    };
+
+    //open the file, handle errors
+    let mut file = match File::open(&path) {
+        Ok(file) => file,
+        Err(error_message) => {
+            println!("file open error message{}", error_message); 
+
+            //check the error kind to respons accordingly
+            return match error_message.kind() {
+                ErrorKind::NotFound => response_404(),
+                _ => status_response(StatusCode::InternalServerError),
+            };
+        },
+    };
+
+    let metadata = match file.metadata() {
+        Ok(metadata) => metadata,
+        Err(error_message) => {
+            println!("{}", error_message); 
+            return status_response(StatusCode::InternalServerError);
+        },
+    };
+
+    let etag = file_etag(&metadata);
+    let last_modified = metadata.modified().ok();
+
+    //headers, which are sent with the file and with 304
+    let mut response = HTTPResponse::new(StatusCode::Ok).header("ETag", &etag);
+
+    if let Some(last_modified) = last_modified {
+        response = response.header("Last-Modified", &http_date(last_modified));
+    }
+
+    if let Some(cache_control) = cache_control_for_path(&request.request_line.path) {
+        response = response.header("Cache-Control", cache_control);
+    }
+
+    //the client has the current version, the file is not read
+    if not_modified(request, &etag, last_modified) {
+        response.status = StatusCode::NotModified;
+        return response;
+    }
+
+    //the start of the content is used, if the file type is unknown
+    let file_start = match read_file_start(&mut file) {
+        Ok(file_start) => file_start,
+        Err(error_message) => {
+            println!("{}", error_message); 
+            return status_response(StatusCode::InternalServerError);
+        },
+    };
+
+    let content_type = content_type_for_path(&path, &file_start);
+    let file_length = metadata.len();
+
+    response = response.header("Accept-Ranges", "bytes");
+
+    let ranges = match request.headers.get("Range") {
+        Some(range) if if_range_matches(request, &etag, last_modified) => parse_range_header(range, file_length),
+        _ => ByteRanges::Ignore,
+    };
+
+    match ranges {
+        ByteRanges::Ignore => {
+            response
+                .header("Content-Type", &content_type)
+                .body_file(file, file_length)
+        },
+        ByteRanges::Unsatisfiable => {
+            status_response(StatusCode::RangeNotSatisfiable)
+                .header("Content-Range", &format!("bytes */{}", file_length))
+        },
+        ByteRanges::Satisfiable(ranges) if ranges.len() == 1 => {
+            let (start, end) = ranges[0];
+
+            if let Err(error_message) = file.seek(SeekFrom::Start(start)) {
+                println!("{}", error_message); 
+                return status_response(StatusCode::InternalServerError);
+            }
+
+            response.status = StatusCode::PartialContent;
+            response
+                .header("Content-Type", &content_type)
+                .header("Content-Range", &format!("bytes {}-{}/{}", start, end, file_length))
+                .body_file(file, end - start + 1)
+        },
+        ByteRanges::Satisfiable(ranges) => {
+            let (boundary, body, length) = match multipart_byteranges(&file, &ranges, file_length, &content_type) {
+                Ok(multipart) => multipart,
+                Err(error_message) => {
+                    println!("{}", error_message); 
+                    return status_response(StatusCode::InternalServerError);
+                },
+            };
+
+            response.status = StatusCode::PartialContent;
+            response
+                .header("Content-Type", &format!("multipart/byteranges; boundary={}", boundary))
+                .body_reader(body, length)
+        },
+    }
+}
+
+pub type Handler = Box<dyn Fn(&HTTPRequest, Arc<DatabaseConnectionPool>) -> HTTPResponse + Send + Sync>;
+
+//code which runs around the handlers of a group of routes
+//a middleware can change the request before calling next, change the response afterwards
+//or return its own response without calling next
+pub trait Middleware: Send + Sync {
+    fn handle (
+        &self, 
+        request: &mut HTTPRequest, 
+        database_connections: &Arc<DatabaseConnectionPool>, 
+        next: Next
+    ) -> HTTPResponse;
+}
+
+//what runs after the last middleware
+enum NextTarget<'a> {
+    //middleware of a path prefix, the router selects the route afterwards
+    Router(&'a Router),
+    //middleware of a single route
+    Handler(&'a Handler),
+}
+
+//the remaining middleware and the router or handler
+pub struct Next<'a> {
+    middleware: &'a [&'a dyn Middleware],
+    target: NextTarget<'a>,
+}
+
+impl Next<'_> {
+    //call the next middleware, or the handler of the route after the last middleware
+    pub fn run (self, request: &mut HTTPRequest, database_connections: &Arc<DatabaseConnectionPool>) -> HTTPResponse {
+        match self.middleware.split_first() {
+            Some((middleware, rest)) => {
+                let next = Next { middleware: rest, target: self.target };
+                middleware.handle(request, database_connections, next)
+            },
+            None => match self.target {
+                NextTarget::Router(router) => router.dispatch(request, database_connections),
+                NextTarget::Handler(handler) => handler(request, Arc::clone(database_connections)),
+            },
+        }
+    }
+}
+
+enum PatternSegment {
+    //segment has to match exactly
+    Static(String),
+    //':name' matches one segment
+    Param(String),
+    //'*name' matches the rest of the path, can only be the last segment
+    Wildcard(String),
+}
+
+pub struct Route {
+    method: Method,
+    pattern: Vec<PatternSegment>,
+    handler: Handler,
+    //middleware, which only runs for this route, after the middleware of the path prefixes
+    middleware: Vec<Box<dyn Middleware>>,
+}
+
+impl Route {
+    fn new (method: Method, pattern: &str, handler: Handler) -> Route {
+        let pattern = pattern
+            .trim_start_matches('/')
+            .split('/')
+            .map(|segment| {
+                if let Some(name) = segment.strip_prefix(':') {
+                    PatternSegment::Param(name.to_string())
+                } else if let Some(name) = segment.strip_prefix('*') {
+                    PatternSegment::Wildcard(name.to_string())
+                } else {
+                    PatternSegment::Static(segment.to_string())
+                }
+            })
+            .collect();
+
+        Route { method, pattern, handler, middleware: Vec::new() }
+    }
+
+    //add a middleware for this route only
+    pub fn with<M: Middleware + 'static> (&mut self, middleware: M) -> &mut Route {
+        self.middleware.push(Box::new(middleware));
+        self
+    }
+
+    //only users with the role can use this route
+    pub fn require_role (&mut self, role: &str) -> &mut Route {
+        self.with(middleware::RequireRole::new(role))
+    }
+
+    //check if the path matches the pattern of the route
+    //returns the values of the parameters, if the path matches
+    fn match_path (&self, path: &str) -> Option<HashMap<String, String>> {
+        let mut params = HashMap::new();
+        let mut path_segments = path.trim_start_matches('/').split('/');
+
+        for pattern_segment in &self.pattern {
+            match pattern_segment {
+                PatternSegment::Static(name) => {
+                    if path_segments.next()? != name {
+                        return None;
+                    }
+                },
+                PatternSegment::Param(name) => {
+                    let value = path_segments.next()?;
+                    if value.is_empty() {
+                        return None;
+                    }
+                    params.insert(name.clone(), percent_decode_string(value));
+                },
+                PatternSegment::Wildcard(name) => {
+                    let rest: Vec<&str> = path_segments.by_ref().collect();
+                    params.insert(name.clone(), percent_decode_string(&rest.join("/")));
+                    return Some(params);
+                },
+            }
+        }
+
+        //the path must not have more segments than the pattern
+        match path_segments.next() {
+            Some(_) => None,
+            None => Some(params),
+        }
+    }
+}
+
+//path with a leading '/' and without empty segments, '//api//login' becomes '/api/login'
+//a trailing '/' is kept, so directory paths stay recognizable
+pub fn normalize_path (path: &str) -> String {
+    let mut normalized = String::from("/");
+    normalized.push_str(
+        &path.split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect::<Vec<&str>>()
+            .join("/")
+    );
+
+    if path.ends_with('/') && normalized.len() > 1 {
+        normalized.push('/');
+    }
+
+    normalized
+}
+
+//calls the handler, which is registered for the method and path of the request
+//routes are checked in the order they have been added
+pub struct Router {
+    routes: Vec<Route>,
+    //middleware with the path prefix it applies to
+    middleware: Vec<(String, Box<dyn Middleware>)>,
+    //route for paths, which match no other route
+    fallback: Option<Route>,
+}
+
+//result of looking up the route for the method and path of a request
+enum RouteMatch<'a> {
+    //the route and the values of its parameters
+    Found(&'a Route, HashMap<String, String>),
+    //the path exists, but only for these methods
+    MethodNotAllowed(Vec<Method>),
+    NotFound,
+}
+
+impl Router {
+    pub fn new () -> Router {
+        Router { routes: Vec::new(), middleware: Vec::new(), fallback: None }
+    }
+
+    //add a middleware for all paths starting with the prefix, '/' for every path
+    //middleware runs in the order it has been added
+    pub fn middleware<M: Middleware + 'static> (&mut self, prefix: &str, middleware: M) {
+        self.middleware.push((prefix.to_string(), Box::new(middleware)));
+    }
+
+    //add a handler for a method and a path pattern
+    //example patterns: '/api/login/logon', '/api/users/:id', '/static/*path'
+    pub fn route<F> (&mut self, method: Method, pattern: &str, handler: F) -> &mut Route
+    where
+        F: Fn(&HTTPRequest, Arc<DatabaseConnectionPool>) -> HTTPResponse + Send + Sync + 'static,
+    {
+        self.routes.push(Route::new(method, pattern, Box::new(handler)));
+        self.routes.last_mut().unwrap()
+    }
+
+    //add a handler for requests, whose path matches no route
+    //paths of routes answer 405 for other methods, they never reach the fallback
+    pub fn fallback<F> (&mut self, method: Method, handler: F) -> &mut Route
+    where
+        F: Fn(&HTTPRequest, Arc<DatabaseConnectionPool>) -> HTTPResponse + Send + Sync + 'static,
+    {
+        self.fallback.insert(Route::new(method, "/*path", Box::new(handler)))
+    }
+
+    pub fn get<F> (&mut self, pattern: &str, handler: F) -> &mut Route
+    where
+        F: Fn(&HTTPRequest, Arc<DatabaseConnectionPool>) -> HTTPResponse + Send + Sync + 'static,
+    {
+        self.route(Method::GET, pattern, handler)
+    }
+
+    pub fn post<F> (&mut self, pattern: &str, handler: F) -> &mut Route
+    where
+        F: Fn(&HTTPRequest, Arc<DatabaseConnectionPool>) -> HTTPResponse + Send + Sync + 'static,
+    {
+        self.route(Method::POST, pattern, handler)
+    }
+
+    //run the middleware for the path of the request and the handler, then send the response
+    pub fn handle (&self, mut request: HTTPRequest, database_connections: Arc<DatabaseConnectionPool>) {
+
+        //the middleware and the routes are selected with the same path
+        //otherwise '//api/...' would reach the routes of '/api/' without their middleware
+        request.request_line.path = normalize_path(&request.request_line.path);
+
+        let middleware: Vec<&dyn Middleware> = self.middleware
+            .iter()
+            .filter(|(prefix, _)| request.request_line.path.starts_with(prefix.as_str()))
+            .map(|(_, middleware)| middleware.as_ref())
+            .collect();
+
+        let next = Next { middleware: &middleware, target: NextTarget::Router(self) };
+        let response = next.run(&mut request, &database_connections);
+
+        send_response(request, response);
+    }
+
+    //find the route for the method and path
+    //the fallback is only used, if no route matches the path
+    fn find_route (&self, method: Method, path: &str) -> RouteMatch<'_> {
+
+        //methods of the routes, whose pattern matches the path
+        let mut allowed_methods: Vec<Method> = Vec::new();
+
+        for route in &self.routes {
+            if let Some(params) = route.match_path(path) {
+                if route.method == method {
+                    return RouteMatch::Found(route, params);
+                }
+
+                if !allowed_methods.contains(&route.method) {
+                    allowed_methods.push(route.method);
+                }
+            }
+        }
+
+        //the path exists, but not for this method
+        if !allowed_methods.is_empty() {
+            return RouteMatch::MethodNotAllowed(allowed_methods);
+        }
+
+        match &self.fallback {
+            Some(fallback) if fallback.method == method => match fallback.match_path(path) {
+                Some(params) => RouteMatch::Found(fallback, params),
+                None => RouteMatch::NotFound,
+            },
+            _ => RouteMatch::NotFound,
+        }
+    }
+
+    //call the handler of the matching route
+    fn dispatch (&self, request: &mut HTTPRequest, database_connections: &Arc<DatabaseConnectionPool>) -> HTTPResponse {
+        match self.find_route(request.request_line.method, &request.request_line.path) {
+            RouteMatch::Found(route, params) => {
+                request.path_params = params;
+
+                let middleware: Vec<&dyn Middleware> = route.middleware
+                    .iter()
+                    .map(|middleware| middleware.as_ref())
+                    .collect();
+
+                let next = Next { middleware: &middleware, target: NextTarget::Handler(&route.handler) };
+                next.run(request, database_connections)
+            },
+            RouteMatch::MethodNotAllowed(allowed_methods) => {
+                let allow = allowed_methods
+                    .iter()
+                    .map(|method| method.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(", ");
+
+                status_response(StatusCode::MethodNotAllowed).header("Allow", &allow)
+            },
+            RouteMatch::NotFound => response_404(),
+        }
+    }
+}
+
+impl Default for Router {
+    fn default () -> Router {
+        Router::new()
+    }
+}
+
+//routes of the server
+pub fn create_router () -> Router {
+    let mut router = Router::new();
+
+    //log every request
+    router.middleware("/", middleware::AccessLog);
+
+    //api calls only receive small json bodies
+    router.middleware("/api/", middleware::BodyLimit::new(64 * 1024));
+
+    //origins of the own pages for the csrf check
+    let allowed_origins = allowed_origins();
+
+    //api calls
+    router.post("/api/login/logon", api::login::api_login_logon);
+    router.post("/api/login/auto_logon", api::login::api_login_auto_logon);
+    router.post("/api/login/refresh", api::login::api_login_refresh)
+        .with(middleware::Csrf::new(&allowed_origins))
+        .with(middleware::TokenAuth);
+    router.post("/api/login/logout", api::login::api_login_logout)
+        .with(middleware::Csrf::new(&allowed_origins))
+        .with(middleware::TokenAuth);
+    router.post("/api/login/logout_all", api::login::api_login_logout_all)
+        .with(middleware::Csrf::new(&allowed_origins))
+        .with(middleware::TokenAuth);
+    router.post("/api/auth/auth_user", api::auth::api_auth_auth_user);
+    router.post("/api/user/register", api::user::api_user_register);
+    router.post("/api/user/change_password", api::user::api_user_change_password)
+        .with(middleware::Csrf::new(&allowed_origins))
+        .with(middleware::TokenAuth);
+    router.post("/api/admin/rehash_passwords", api::admin::api_admin_rehash_passwords)
+        .with(middleware::Csrf::new(&allowed_origins))
+        .require_role("admin");
+
+    //everything else is a static file
+    router.fallback(Method::GET, serve_static_file);
+
+    router
+}
+
+//seconds a token is valid after it has been issued
+pub const TOKEN_ABSOLUTE_LIFETIME: i64 = 30 * 24 * 60 * 60;
+//seconds a token is valid after it has been used the last time
+pub const TOKEN_IDLE_LIFETIME: i64 = 7 * 24 * 60 * 60;
+
+//user, to whom a valid token belongs
+#[derive(Debug, Clone)]
+pub struct TokenUser {
+    //session of the token, a user has one session per device
+    pub session_id: i64,
+    pub id: i64,
+    pub username: String,
+    pub remember_login: bool,
+}
+
+//authenticated user of a request with the roles from the database
+#[derive(Debug, Clone)]
+pub struct Principal {
+    pub user_id: i64,
+    pub username: String,
+    pub session_id: i64,
+    //the session keeps its cookie, when the browser is closed
+    pub remember_login: bool,
+    pub roles: Vec<String>,
+}
+
+impl Principal {
+    pub fn has_role (&self, role: &str) -> bool {
+        self.roles.iter().any(|user_role| user_role == role)
+    }
+}
+
+//load the roles of the user, to whom a valid token belongs
+pub fn load_principal (
+    database_connections: &Arc<DatabaseConnectionPool>, 
+    user: TokenUser
+) -> Result<Principal, DatabaseError> {
+
+    let query = "SELECT role FROM user_roles WHERE userid = $1 ORDER BY role";
+
+    let mut db_con = DatabaseConnectionPool::get_connection(database_connections).unwrap();
+    let data = db_con.query_params(query, &[&user.id]);
+    database_connections.release_connection(db_con);
+
+    let roles = data?
+        .iter()
+        .filter_map(|row| match row.get("role") {
+            Some(Some(DatabaseValue::Varchar(role))) => Some(role.clone()),
+            _ => None,
+        })
+        .collect();
+
+    Ok(Principal {
+        user_id: user.id,
+        username: user.username,
+        session_id: user.session_id,
+        remember_login: user.remember_login,
+        roles,
+    })
+}
+
+//result of checking a token from a client
+#[derive(Debug, Clone)]
+pub enum TokenCheck {
+    Valid(TokenUser),
+    Unknown,
+    //the absolute lifetime is over
+    Expired,
+    //the token has not been used for longer than the idle lifetime
+    Idle,
+}
+
+impl TokenCheck {
+    //reason for the rejection of the token, which is sent to the client
+    pub fn message (&self) -> &'static str {
+        match self {
+            TokenCheck::Valid(_) => "User successfully authenticated",
+            TokenCheck::Unknown => "User could not be authenticated",
+            TokenCheck::Expired => "Token has expired",
+            TokenCheck::Idle => "Token has expired because it has not been used",
+        }
+    }
+}
+
+//seconds since the unix epoch
+pub fn unix_time () -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_secs() as i64).unwrap_or(0)
+}
+
+//tokens are only stored as hash, so a copy of the database does not contain usable tokens
+//the tokens are random, so a fast hash without salt is enough
+pub fn hash_token (token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+//integer column of a row, the size of the integer type in the database does not matter
+pub fn database_value_i64 (value: Option<&Option<DatabaseValue>>) -> Option<i64> {
+    match value {
+        Some(Some(DatabaseValue::Integer(int))) => Some(*int as i64),
+        Some(Some(DatabaseValue::BigInteger(int))) => Some(*int),
+        _ => None,
+    }
+}
+
+//check if a token belongs to a user and is not expired
+//a valid token counts as used, which restarts the idle lifetime
+pub fn check_token (
+    database_connections: &Arc<DatabaseConnectionPool>, 
+    token: &str
+) -> Result<TokenCheck, DatabaseError> {
+
+    if !token_format_valid(token, TOKEN_PREFIX) {
+        return Ok(TokenCheck::Unknown);
+    }
+
+    let token_hash = hash_token(token);
+
+    let query = "SELECT sessions.id AS sessionid, sessions.token, users.id, users.username, \
+        sessions.rememberlogin, sessions.created, sessions.lastused \
+        FROM sessions JOIN users ON users.id = sessions.userid WHERE sessions.token = $1";
+
+    let mut db_con = DatabaseConnectionPool::get_connection(database_connections).unwrap();
+    let data = db_con.query_params(query, &[&token_hash]);
+    database_connections.release_connection(db_con);
+
+    let data = data?;
+
+    let row = match data.first() {
+        Some(row) => row,
+        None => return Ok(TokenCheck::Unknown),
+    };
+
+    //the database only finds the row by the hash, the comparison of the stored hash
+    //is done again without leaking through the time where the values differ
+    let hash_matches = match row.get("token") {
+        Some(Some(DatabaseValue::Varchar(stored_hash))) => constant_time_eq(stored_hash.as_bytes(), token_hash.as_bytes()),
+        _ => false,
+    };
+
+    if !hash_matches {
+        return Ok(TokenCheck::Unknown);
+    }
+
+    let now = unix_time();
+    let session_id = database_value_i64(row.get("sessionid")).unwrap_or(0);
+    let created = database_value_i64(row.get("created")).unwrap_or(0);
+    let last_used = database_value_i64(row.get("lastused")).unwrap_or(created);
+
+    let token_check = if now - created > TOKEN_ABSOLUTE_LIFETIME {
+        Some(TokenCheck::Expired)
+    } else if now - last_used > TOKEN_IDLE_LIFETIME {
+        Some(TokenCheck::Idle)
+    } else {
+        None
+    };
+
+    //expired sessions are removed
+    if let Some(token_check) = token_check {
+        delete_session(database_connections, session_id)?;
+        return Ok(token_check);
+    }
+
+    let user = TokenUser {
+        session_id,
+        id: database_value_i64(row.get("id")).unwrap_or(0),
+        username: match row.get("username") {
+            Some(Some(DatabaseValue::Varchar(username))) => username.clone(),
+            _ => String::new(),
+        },
+        remember_login: matches!(row.get("rememberlogin"), Some(Some(DatabaseValue::Boolean(true)))),
+    };
+
+    let query = "UPDATE sessions SET lastused = $1 WHERE id = $2";
+
+    let mut db_con = DatabaseConnectionPool::get_connection(database_connections).unwrap();
+    let result = db_con.query_params(query, &[&now, &session_id]);
+    database_connections.release_connection(db_con);
+
+    result?;
+
+    Ok(TokenCheck::Valid(user))
+}
+
+//create the tables, which are managed by the server, if they do not exist
+pub fn create_tables (database_connections: &Arc<DatabaseConnectionPool>) -> Result<(), DatabaseError> {
+    //one row per issued token, so a user can be logged in on several devices
+    let query = "CREATE TABLE IF NOT EXISTS sessions (\
+        id bigserial PRIMARY KEY, \
+        userid integer NOT NULL REFERENCES users(id) ON DELETE CASCADE, \
+        token varchar(64) NOT NULL UNIQUE, \
+        created bigint NOT NULL, \
+        lastused bigint NOT NULL, \
+        rememberlogin boolean NOT NULL DEFAULT false)";
+
+    //registration relies on the database to reject duplicate usernames
+    let index_query = "CREATE UNIQUE INDEX IF NOT EXISTS users_username_key ON users (username)";
+
+    //roles, which are required by routes, for example 'admin'
+    let roles_query = "CREATE TABLE IF NOT EXISTS user_roles (\
+        userid integer NOT NULL REFERENCES users(id) ON DELETE CASCADE, \
+        role varchar(64) NOT NULL, \
+        PRIMARY KEY (userid, role))";
+
+    let mut db_con = DatabaseConnectionPool::get_connection(database_connections).unwrap();
+    let result = db_con.query(query)
+        .and_then(|_| db_con.query(index_query))
+        .and_then(|_| db_con.query(roles_query));
+    database_connections.release_connection(db_con);
+
+    result.map(|_| ())
+}
+
+//start a new session for the user, returns the token for the client
+pub fn create_session (
+    database_connections: &Arc<DatabaseConnectionPool>, 
+    user_id: i64, 
+    remember_login: bool
+) -> Result<String, DatabaseError> {
+
+    let token = generate_token();
+    let now = unix_time();
+
+    //only the hash of the token is stored
+    let query = "INSERT INTO sessions (userid, token, created, lastused, rememberlogin) VALUES ($1, $2, $3, $3, $4)";
+
+    let mut db_con = DatabaseConnectionPool::get_connection(database_connections).unwrap();
+    let result = db_con.query_params(query, &[&user_id, &hash_token(&token), &now, &remember_login]);
+    database_connections.release_connection(db_con);
+
+    result?;
+
+    Ok(token)
+}
+
+pub fn delete_session (database_connections: &Arc<DatabaseConnectionPool>, session_id: i64) -> Result<(), DatabaseError> {
+    let query = "DELETE FROM sessions WHERE id = $1";
+
+    let mut db_con = DatabaseConnectionPool::get_connection(database_connections).unwrap();
+    let result = db_con.query_params(query, &[&session_id]);
+    database_connections.release_connection(db_con);
+
+    result.map(|_| ())
+}
+
+//end every session of the user, returns the number of ended sessions
+pub fn delete_user_sessions (database_connections: &Arc<DatabaseConnectionPool>, user_id: i64) -> Result<usize, DatabaseError> {
+    let query = "DELETE FROM sessions WHERE userid = $1 RETURNING id";
+
+    let mut db_con = DatabaseConnectionPool::get_connection(database_connections).unwrap();
+    let result = db_con.query_params(query, &[&user_id]);
+    database_connections.release_connection(db_con);
+
+    result.map(|data| data.len())
+}
+
+//name of the cookie, which holds the token of the session
+pub const SESSION_COOKIE: &str = "session";
+
+//file with the origins, under which the pages of the server are reached, 'https://host:port'
+//one origin per line, '#' starts a comment
+pub const ORIGINS_CONFIG_FILE: &str = "origins.conf";
+//used, if the config file is missing or empty
+pub const DEFAULT_ALLOWED_ORIGINS: &[&str] = &["http://212.132.120.118:7878"];
+
+//read the origins from the config file
+pub fn load_allowed_origins (path: &str) -> Vec<String> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) => {
+            if err.kind() != ErrorKind::NotFound {
+                println!("could not read origins config {}: {}", path, err);
+            }
+            String::new()
+        },
+    };
+
+    let origins: Vec<String> = content
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or("").trim().trim_end_matches('/'))
+        .filter(|origin| !origin.is_empty())
+        .map(|origin| origin.to_string())
+        .collect();
+
+    if origins.is_empty() {
+        return DEFAULT_ALLOWED_ORIGINS.iter().map(|origin| origin.to_string()).collect();
+    }
+
+    origins
+}
+
+static ALLOWED_ORIGINS: LazyLock<Vec<String>> = LazyLock::new(|| load_allowed_origins(ORIGINS_CONFIG_FILE));
+
+//origins, from which requests with the session cookie are accepted
+pub fn allowed_origins () -> Vec<&'static str> {
+    ALLOWED_ORIGINS.iter().map(|origin| origin.as_str()).collect()
+}
+
+//the cookies are only marked secure, if the server is reached over https only
+//browsers do not store secure cookies, which are received over plain http
+pub fn session_cookie_secure () -> bool {
+    ALLOWED_ORIGINS.iter().all(|origin| origin.to_lowercase().starts_with("https://"))
+}
+
+//cookie with the token of a session, which can not be read by javascript
+//sessions with remember login keep the cookie when the browser is closed
+pub fn session_cookie (token: &str, remember_login: bool) -> Cookie {
+    let cookie = Cookie::new(SESSION_COOKIE, token)
+        .path("/")
+        .http_only(true)
+        .secure(session_cookie_secure())
+        .same_site(SameSite::Strict);
+
+    match remember_login {
+        true => cookie.max_age(TOKEN_ABSOLUTE_LIFETIME),
+        false => cookie,
+    }
+}
+
+//name of the cookie with the csrf token, which the client sends back in the header X-CSRF-Token
+pub const CSRF_COOKIE: &str = "csrf";
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+//cookie with a new csrf token, it can be read by javascript to be copied into the header
+//other sites can neither read the cookie nor set the header, so only the own pages can send both
+pub fn csrf_cookie (remember_login: bool) -> Cookie {
+    let cookie = Cookie::new(CSRF_COOKIE, &generate_prefixed_token(CSRF_TOKEN_PREFIX, TOKEN_BYTES))
+        .path("/")
+        .secure(session_cookie_secure())
+        .same_site(SameSite::Strict);
+
+    match remember_login {
+        true => cookie.max_age(TOKEN_ABSOLUTE_LIFETIME),
+        false => cookie,
+    }
+}
+
+//removes the session cookie from the browser
+pub fn session_cookie_removal () -> Cookie {
+    Cookie::removal(SESSION_COOKIE)
+        .path("/")
+        .http_only(true)
+        .secure(session_cookie_secure())
+        .same_site(SameSite::Strict)
+}
+
+//token of the client, from the json field 'UserToken', the header 'Authorization: Bearer <token>'
+//or the session cookie, in this order
+pub fn request_token (request: &HTTPRequest) -> String {
+    if let Ok(post_data) = parse_json(&request.body) {
+        if let Some(JsonType::String(token)) = post_data.get("UserToken") {
+            return token.to_string();
+        }
+    }
+
+    if let Some(authorization) = request.headers.get("Authorization") {
+        if let Some((scheme, token)) = authorization.split_once(' ') {
+            if scheme.eq_ignore_ascii_case("Bearer") {
+                return token.trim().to_string();
+            }
+        }
+    }
+
+    request.cookie(SESSION_COOKIE).unwrap_or_default()
+}
+
+//prefix of session tokens, so scanners can find leaked tokens
+pub const TOKEN_PREFIX: &str = "rws_";
+//prefix of csrf tokens
+pub const CSRF_TOKEN_PREFIX: &str = "rwc_";
+//random bytes of a token, 32 bytes are 256 bits
+pub const TOKEN_BYTES: usize = 32;
+//length of the checksum at the end of a token in characters
+const TOKEN_CHECKSUM_LENGTH: usize = 6;
+
+//session token from the random generator of the operating system
+pub fn generate_token () -> String {
+    generate_prefixed_token(TOKEN_PREFIX, TOKEN_BYTES)
+}
+
+//token in the format '<prefix><random bytes as base64url><checksum>'
+//the checksum lets scanners tell real tokens from random strings without asking the server
+pub fn generate_prefixed_token (prefix: &str, length: usize) -> String {
+    let mut bytes = vec![0; length];
+    OsRng.fill_bytes(&mut bytes);
+
+    let random = BASE64_URL.encode(&bytes);
+    let checksum = token_checksum(prefix, &random);
+
+    format!("{}{}{}", prefix, random, checksum)
+}
+
+fn token_checksum (prefix: &str, random: &str) -> String {
+    let digest = Sha256::digest(format!("{}{}", prefix, random).as_bytes());
+
+    //6 characters of base64 hold 36 bits, 4 bytes are enough
+    let mut checksum = BASE64_URL.encode(&digest[..4]);
+    checksum.truncate(TOKEN_CHECKSUM_LENGTH);
+    checksum
+}
+
+//check prefix and checksum of a token, tokens with typos or made up tokens are rejected
+//without a database query
+pub fn token_format_valid (token: &str, prefix: &str) -> bool {
+    let rest = match token.strip_prefix(prefix) {
+        Some(rest) => rest,
+        None => return false,
+    };
+
+    if rest.len() <= TOKEN_CHECKSUM_LENGTH || !rest.is_ascii() {
+        return false;
+    }
+
+    let (random, checksum) = rest.split_at(rest.len() - TOKEN_CHECKSUM_LENGTH);
+
+    constant_time_eq(token_checksum(prefix, random).as_bytes(), checksum.as_bytes())
+}
+
+//result of checking a password against the value stored in the database
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PasswordCheck {
+    Valid,
+    //the password is correct, but stored in plaintext or with old parameters
+    ValidNeedsRehash,
+    Invalid,
+}
+
+//hash a password with argon2id and a random salt
+//the result is a PHC string, which contains algorithm, parameters and salt
+pub fn hash_password (password: &str) -> Result<String, argon2::password_hash::Error> {
+    let salt = SaltString::generate(&mut OsRng);
+
+    Ok(Argon2::default().hash_password(password.as_bytes(), &salt)?.to_string())
+}
+
+//check a password against the stored value
+//values, which are not PHC strings, are passwords from before hashing was introduced
+pub fn verify_password (password: &str, stored: &str) -> PasswordCheck {
+    let hash = match PasswordHash::new(stored) {
+        Ok(hash) => hash,
+        Err(_) => {
+            //a plaintext password is checked as slow as a hash, so the response time
+            //does not tell apart users with plaintext passwords and unknown users
+            verify_dummy_password(password);
+
+            return match constant_time_eq(password.as_bytes(), stored.as_bytes()) {
+                true => PasswordCheck::ValidNeedsRehash,
+                false => PasswordCheck::Invalid,
+            };
+        },
+    };
+
+    //the comparison of the hashes is done in constant time by argon2
+    if Argon2::default().verify_password(password.as_bytes(), &hash).is_err() {
+        return PasswordCheck::Invalid;
+    }
+
+    if password_needs_rehash(&hash) {
+        PasswordCheck::ValidNeedsRehash
+    } else {
+        PasswordCheck::Valid
+    }
+}
+
+//check if the hash has not been created with the current algorithm and parameters
+fn password_needs_rehash (hash: &PasswordHash) -> bool {
+    let current = Argon2::default();
+
+    if hash.algorithm != argon2::Algorithm::Argon2id.ident() || hash.version != Some(argon2::Version::V0x13.into()) {
+        return true;
+    }
+
+    match argon2::Params::try_from(hash) {
+        Ok(params) => {
+            params.m_cost() != current.params().m_cost()
+                || params.t_cost() != current.params().t_cost()
+                || params.p_cost() != current.params().p_cost()
+        },
+        Err(_) => true,
+    }
+}
+
+//check a password for a user, who does not exist
+//takes as long as checking a real password, so the response time does not tell if the user exists
+pub fn verify_dummy_password (password: &str) {
+    static DUMMY_HASH: LazyLock<String> = LazyLock::new(|| {
+        hash_password(&generate_token()).unwrap_or_default()
+    });
+
+    //verified directly, verify_password checks plaintext passwords with this function
+    if let Ok(hash) = PasswordHash::new(&DUMMY_HASH) {
+        let _ = Argon2::default().verify_password(password.as_bytes(), &hash);
+    }
+}
+
+//failed logins, which are allowed before the client has to wait
+pub const LOGIN_FREE_ATTEMPTS: u32 = 5;
+//longest time a client has to wait after failed logins
+pub const LOGIN_MAX_LOCKOUT: Duration = Duration::from_secs(15 * 60);
+//failed logins are forgotten after this time without a new failure
+pub const LOGIN_FAILURE_MEMORY: Duration = Duration::from_secs(60 * 60);
+//most usernames and ip addresses, whose failed logins are counted at the same time
+pub const LOGIN_MAX_ENTRIES: usize = 10_000;
+
+struct FailedLogins {
+    count: u32,
+    last_failure: Instant,
+}
+
+impl FailedLogins {
+    //time until the next attempt is allowed, doubles with every failure after the free attempts
+    fn locked_until (&self) -> Option<Instant> {
+        if self.count < LOGIN_FREE_ATTEMPTS {
+            return None;
+        }
+
+        let exponent = (self.count - LOGIN_FREE_ATTEMPTS).min(16);
+        let lockout = Duration::from_secs(1 << exponent).min(LOGIN_MAX_LOCKOUT);
+
+        Some(self.last_failure + lockout)
+    }
+}
+
+//counts failed logins per username and per ip address
+//the counters are kept in memory, so they are reset when the server restarts
+pub struct LoginThrottle {
+    failures: Mutex<HashMap<String, FailedLogins>>,
+}
+
+impl LoginThrottle {
+    pub fn new () -> LoginThrottle {
+        LoginThrottle { failures: Mutex::new(HashMap::new()) }
+    }
+
+    fn keys (username: &str, ip: &str) -> [String; 2] {
+        [format!("user:{}", username.to_lowercase()), format!("ip:{}", ip)]
+    }
+
+    //returns how long the client has to wait, before a login is attempted again
+    pub fn retry_after (&self, username: &str, ip: &str) -> Option<Duration> {
+        let failures = self.failures.lock().unwrap();
+        let now = Instant::now();
+
+        LoginThrottle::keys(username, ip)
+            .iter()
+            .filter_map(|key| failures.get(key))
+            .filter_map(|failed| failed.locked_until())
+            .filter(|locked_until| *locked_until > now)
+            .map(|locked_until| locked_until - now)
+            .max()
+    }
+
+    pub fn record_failure (&self, username: &str, ip: &str) {
+        let mut failures = self.failures.lock().unwrap();
+        let now = Instant::now();
+
+        //remove old entries, so the map does not grow without limit
+        failures.retain(|_, failed| now.duration_since(failed.last_failure) < LOGIN_FAILURE_MEMORY);
+
+        for key in LoginThrottle::keys(username, ip) {
+            //many different usernames or addresses within the memory time
+            //replace the entry with the oldest failure
+            if failures.len() >= LOGIN_MAX_ENTRIES && !failures.contains_key(&key) {
+                let oldest = failures
+                    .iter()
+                    .min_by_key(|(_, failed)| failed.last_failure)
+                    .map(|(key, _)| key.clone());
+
+                if let Some(oldest) = oldest {
+                    failures.remove(&oldest);
+                }
+            }
+
+            let failed = failures.entry(key).or_insert(FailedLogins { count: 0, last_failure: now });
+            failed.count += 1;
+            failed.last_failure = now;
+        }
+    }
+
+    //a successful login resets the counter of the username
+    //the counter of the ip address is kept, so one account can not be used to reset it
+    pub fn record_success (&self, username: &str, ip: &str) {
+        let [user_key, _] = LoginThrottle::keys(username, ip);
+        self.failures.lock().unwrap().remove(&user_key);
+    }
+}
+
+impl Default for LoginThrottle {
+    fn default () -> LoginThrottle {
+        LoginThrottle::new()
+    }
+}
+
+//shared by all threads
+pub static LOGIN_THROTTLE: LazyLock<LoginThrottle> = LazyLock::new(LoginThrottle::new);
+
+//compare two byte slices, the time does not depend on where they differ
+pub fn constant_time_eq (a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter().zip(b.iter()).fold(0, |difference, (x, y)| difference | (x ^ y)) == 0
+}
+
+//hash all passwords, which are still stored in plaintext
+//returns the number of updated users
+pub fn rehash_plaintext_passwords (database_connections: &Arc<DatabaseConnectionPool>) -> Result<usize, DatabaseError> {
+    let mut db_con = DatabaseConnectionPool::get_connection(database_connections).unwrap();
+    let result = rehash_plaintext_passwords_on(&mut db_con);
+    database_connections.release_connection(db_con);
+
+    result
+}
+
+fn rehash_plaintext_passwords_on (db_con: &mut DatabaseConnection) -> Result<usize, DatabaseError> {
+    let data = db_con.query("SELECT id, password FROM users")?;
+
+    let mut updated = 0;
+
+    for row in &data {
+        let (id, password) = match (row.get("id"), row.get("password")) {
+            (Some(Some(id)), Some(Some(DatabaseValue::Varchar(password)))) => (id, password),
+            _ => continue,
+        };
+
+        //already hashed
+        if PasswordHash::new(password).is_ok() {
+            continue;
+        }
+
+        let hash = match hash_password(password) {
+            Ok(hash) => hash,
+            Err(err) => {
+                println!("could not hash password: {}", err);
+                continue;
+            },
+        };
+
+        db_con.query_params("UPDATE users SET password = $1 WHERE id = $2", &[&hash, id])?;
+        updated += 1;
+    }
+
+    Ok(updated)
+}
+
+pub fn api_response_json <T: MatchJsonType> (response: T) -> HTTPResponse {
+    //turn response data into json
+    let json = json_encode(&response);
+
+    HTTPResponse::new(StatusCode::Ok)
+        .header("Content-Type", "application/json")
+        .body(json.into_bytes())
+}
+
+//answer an api call, which could not be executed, with a message
+pub fn api_error (message: &str) -> HTTPResponse {
+    let mut api_response_btreemap: BTreeMap<String, Option<APIValue>> = BTreeMap::new();
+    api_response_btreemap.insert(
+        String::from("Message"), 
+        Some(APIValue::String(message.to_string()))
+    );
+
+    api_response_json(api_response_btreemap)
+}
+
+//answer an api call, which failed because of a database error
+//the details of the error are only written to the log
+pub fn api_database_error (error: DatabaseError) -> HTTPResponse {
+    println!("database error: {}", error);
+
+    api_error("Database error")
+}
+
+//write the response to the client
+//if the client can not be reached anymore, the error is only logged
+pub fn send_response (mut request: HTTPRequest, response: HTTPResponse) {
+    if let Err(err) = response.send(&mut request) {
+        println!("could not send response: {}", err);
+    }
+}
+
+//response with only the status as text
+pub fn status_response (status: StatusCode) -> HTTPResponse {
+    HTTPResponse::new(status)
+        .header("Content-Type", "text/plain")
+        .body(format!("{} {}", status.code(), status.reason()).into_bytes())
+}
+
+pub fn response_404 () -> HTTPResponse {
+
+    //create vector to hold content
+    let mut content_vector = Vec::new();
+
+    //open the file, if there is no 404 page send only the status
+    let read_result = File::open("404.html")
+        .and_then(|mut file| file.read_to_end(&mut content_vector));
+
+    if let Err(err) = read_result {
+        println!("could not read 404 page: {}", err);
+        return status_response(StatusCode::NotFound);
     }
 
-    //return the media types
-    media_types
-    
+    HTTPResponse::new(StatusCode::NotFound)
+        .header("Content-Type", "text/html")
+        .body(content_vector)
+}
+
+//media type for the extension of a file, the extension has to be lower case
+pub fn get_content_type (file_type: &str) -> Option<&'static str> {
+    let content_type = match file_type {
+        //text
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "js" | "mjs" => "text/javascript",
+        "txt" | "text" => "text/plain",
+        "csv" => "text/csv",
+        "md" => "text/markdown",
+        "xml" => "application/xml",
+        "json" | "map" => "application/json",
+        "webmanifest" => "application/manifest+json",
+        "ics" => "text/calendar",
+        //images
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "svg" => "image/svg+xml",
+        "ico" => "image/x-icon",
+        "bmp" => "image/bmp",
+        "tif" | "tiff" => "image/tiff",
+        //fonts
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "eot" => "application/vnd.ms-fontobject",
+        //audio and video
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "ogg" | "oga" => "audio/ogg",
+        "opus" => "audio/opus",
+        "flac" => "audio/flac",
+        "m4a" => "audio/mp4",
+        "mp4" | "m4v" => "video/mp4",
+        "webm" => "video/webm",
+        "ogv" => "video/ogg",
+        "mov" => "video/quicktime",
+        //other
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "tar" => "application/x-tar",
+        "7z" => "application/x-7z-compressed",
+        "rtf" => "application/rtf",
+        _ => return None,
+    };
 
+    Some(content_type)
 }
 
-pub fn read_http_headers (buf_reader: &mut BufReader<&TcpStream>) -> HashMap<String, String> {
+//file with mappings, which replace or extend the built in table
+//every line has the format of mime.types, 'type/subtype ext1 ext2', '#' starts a comment
+pub const MIME_CONFIG_FILE: &str = "mime.types";
 
-    //creat new hash map holding the headers
-    let mut headers_hash = HashMap::new();
+//read the mappings from the config file, a missing file means no overrides
+pub fn load_mime_overrides (path: &str) -> HashMap<String, String> {
+    let mut overrides = HashMap::new();
 
-    //loop through the lines of the header
-    //and extract the individual paramteres
-    loop {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) => {
+            if err.kind() != ErrorKind::NotFound {
+                println!("could not read mime config {}: {}", path, err);
+            }
+            return overrides;
+        },
+    };
 
-        //create new empty string for the header line
-        let mut header_line = String::new();
+    for line in content.lines() {
+        let line = line.split('#').next().unwrap_or("");
+        let mut parts = line.split_whitespace();
 
-        //read new line from the stream
-        buf_reader.read_line(&mut header_line).unwrap();
+        if let Some(content_type) = parts.next() {
+            for extension in parts {
+                overrides.insert(extension.to_lowercase(), content_type.to_string());
+            }
+        }
+    }
 
-        //trim empty spaces from the line
-        let header_line = header_line.trim();
+    overrides
+}
 
-        // if the line is empty, break from the loop
-        // no more lines will be read
-        if header_line.is_empty() {
-            break;
+static MIME_OVERRIDES: LazyLock<HashMap<String, String>> = LazyLock::new(|| load_mime_overrides(MIME_CONFIG_FILE));
+
+//guess the media type from the first bytes of the content
+pub fn sniff_content_type (content: &[u8]) -> Option<&'static str> {
+    let signatures: [(&[u8], &str); 13] = [
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1f\x8b", "application/gzip"),
+        (b"\x00asm", "application/wasm"),
+        (b"wOFF", "font/woff"),
+        (b"wOF2", "font/woff2"),
+        (b"\x00\x00\x01\x00", "image/x-icon"),
+        (b"ID3", "audio/mpeg"),
+        (b"OggS", "audio/ogg"),
+    ];
+
+    for (signature, content_type) in signatures {
+        if content.starts_with(signature) {
+            return Some(content_type);
+        }
+    }
+
+    //formats with the signature not at the start
+    if content.len() >= 12 && &content[0..4] == b"RIFF" {
+        match &content[8..12] {
+            b"WEBP" => return Some("image/webp"),
+            b"WAVE" => return Some("audio/wav"),
+            _ => {},
         }
+    }
+
+    if content.len() >= 12 && &content[4..8] == b"ftyp" {
+        return Some("video/mp4");
+    }
+
+    if content.starts_with(b"\x1a\x45\xdf\xa3") {
+        return Some("video/webm");
+    }
+
+    //text formats, only the start of the content is checked
+    let start = &content[..content.len().min(512)];
+    let text = match std::str::from_utf8(start) {
+        Ok(text) => text,
+        //the cut at 512 bytes can split a character
+        Err(err) if err.error_len().is_none() => std::str::from_utf8(&start[..err.valid_up_to()]).unwrap_or(""),
+        Err(_) => return None,
+    };
 
-        //split the line by ':' to get the description and value of the parameter
-        let mut header_line_split = header_line.split(':');
+    if text.contains('\0') {
+        return None;
+    }
 
-        //put the description and value into variables
-        let header_description = header_line_split.next().unwrap().to_string();
-        let header_value = header_line_split.next().unwrap().trim().to_string();
+    let lower = text.trim_start().to_ascii_lowercase();
 
-        //insert the pair into the hashmap
-        headers_hash.insert(header_description, header_value);
+    if lower.starts_with("<!doctype html") || lower.starts_with("<html") {
+        return Some("text/html");
+    }
 
+    if lower.starts_with("<svg") || (lower.starts_with("<?xml") && lower.contains("<svg")) {
+        return Some("image/svg+xml");
     }
 
+    if lower.starts_with("<?xml") {
+        return Some("application/xml");
+    }
 
-    //return the hash map from the function
-    headers_hash
+    Some("text/plain")
 }
 
-pub fn read_http_body (
-    buf_reader: &mut BufReader<&TcpStream>, 
-    content_length: usize
-) -> String {
+//add the charset to text types, the files are expected to be utf-8
+pub fn content_type_with_charset (content_type: &str) -> String {
+    let is_text = content_type.starts_with("text/")
+        || matches!(
+            content_type,
+            "application/json" | "application/xml" | "application/manifest+json" | "image/svg+xml"
+        );
+
+    match is_text && !content_type.contains("charset") {
+        true => format!("{}; charset=utf-8", content_type),
+        false => content_type.to_string(),
+    }
+}
 
-    //create empty vector with the length of the content
-    let mut body: Vec<u8> = vec![0; content_length];
+//content type header for a file
+//the extension is the part after the last dot of the file name, files without known extension
+//are recognized by their content
+pub fn content_type_for_path (path: &Path, content: &[u8]) -> String {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+
+    let content_type = match extension {
+        Some(extension) => match MIME_OVERRIDES.get(&extension) {
+            Some(content_type) => Some(content_type.as_str()),
+            None => get_content_type(&extension),
+        },
+        None => None,
+    };
 
-    //read content into vector
-    buf_reader.read_exact(&mut body).unwrap(); 
+    let content_type = content_type
+        .or_else(|| sniff_content_type(content))
+        .unwrap_or("application/octet-stream");
 
-    //turn body from bytes into a string
-    let body = std::str::from_utf8(&body).unwrap();
+    content_type_with_charset(content_type)
+}
+
+pub fn parse_json (json_string: &str) -> Result<JsonType, JsonError> {
+    let mut parser = JsonParser { bytes: json_string.as_bytes(), position: 0, depth: 0 };
 
-    body.to_string()
+    let value = parser.parse_value()?;
+
+    //only whitespace is allowed after the value
+    parser.skip_whitespace();
+    if parser.position < parser.bytes.len() {
+        return Err(parser.error("unexpected character after json value"));
+    }
+
+    Ok(value)
 }
 
-pub fn send_http_response (
-    mut request: HTTPRequest, 
-    database_connections: Arc<DatabaseConnectionPool>
-) {
+//maximum nesting of arrays and objects, so deep nesting can not overflow the stack
+const JSON_MAX_DEPTH: usize = 128;
 
-    //create full path by adding root directory
-    let path = match request.request_line.path.as_str() {
-        "/" => format!("{}{}", constants::ROOT, "/login.html"),
-        _ => [constants::ROOT, &request.request_line.path].concat(),
-    };
+//recursive descent parser over the bytes of a json string
+struct JsonParser<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    depth: usize,
+}
 
-    let mut path_split = path.split('/');
+impl JsonParser<'_> {
+    fn error (&self, message: &str) -> JsonError {
+        JsonError { message: message.to_string(), offset: self.position }
+    }
 
-    //check if the path is an api call
-    if let Some(path) = path_split.nth(4) { 
+    fn peek (&self) -> Option<u8> {
+        self.bytes.get(self.position).copied()
+    }
 
-        if path == "api" {
+    fn skip_whitespace (&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.position += 1;
+        }
+    }
 
-            let category = path_split.next().unwrap();
-            let function = path_split.next().unwrap();
+    //check that the next byte matches the expected byte and move beyond it
+    fn expect (&mut self, expected: u8) -> Result<(), JsonError> {
+        match self.peek() {
+            Some(byte) if byte == expected => {
+                self.position += 1;
+                Ok(())
+            },
+            Some(_) => Err(self.error(&format!("expected '{}'", expected as char))),
+            None => Err(self.error("unexpected end of json")),
+        }
+    }
 
-            execute_api_call(request, database_connections, category, function);
-            return;
+    fn parse_value (&mut self) -> Result<JsonType, JsonError> {
+        self.skip_whitespace();
+
+        match self.peek() {
+            Some(b'{') => self.parse_object(),
+            Some(b'[') => self.parse_array(),
+            Some(b'"') => Ok(JsonType::String(self.parse_string()?)),
+            Some(b'-' | b'0'..=b'9') => self.parse_number(),
+            Some(b't') => self.parse_literal("true", JsonType::Boolean(true)),
+            Some(b'f') => self.parse_literal("false", JsonType::Boolean(false)),
+            Some(b'n') => self.parse_literal("null", JsonType::Null),
+            Some(_) => Err(self.error("unexpected character")),
+            None => Err(self.error("unexpected end of json")),
+        }
+    }
 
+    fn parse_literal (&mut self, literal: &str, value: JsonType) -> Result<JsonType, JsonError> {
+        if self.bytes[self.position..].starts_with(literal.as_bytes()) {
+            self.position += literal.len();
+            Ok(value)
+        } else {
+            Err(self.error("invalid literal"))
         }
+    }
 
-    };
+    fn enter_nesting (&mut self) -> Result<(), JsonError> {
+        self.depth += 1;
+        if self.depth > JSON_MAX_DEPTH {
+            return Err(self.error("json is nested too deep"));
+        }
+        Ok(())
+    }
 
-    //get the file type
-    let file_type = match path.split('.').nth(1) {
-        Some(ftype) => ftype,
-        None => "undefined",
+    fn parse_object (&mut self) -> Result<JsonType, JsonError> {
+        self.enter_nesting()?;
+        self.expect(b'{')?;
 
-    };
-    
-    let content_type = get_content_type(file_type);
+        let mut object = BTreeMap::new();
 
-    //create vector to hold content
-    let mut content_vector = Vec::new();
-    //open the file, handle errors
-    let content_file = File::open(&path);
+        //check for empty object
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.position += 1;
+            self.depth -= 1;
+            return Ok(JsonType::Object(object));
+        }
 
-    match content_file {
-        Ok(mut file) => {
-            //read to end into vector, handle errors
-            match file.read_to_end(&mut content_vector) {
-                Ok(_) => {
+        loop {
+            //read the key
+            self.skip_whitespace();
+            if self.peek() != Some(b'"') {
+                return Err(self.error("expected string as object key"));
+            }
+            let key = self.parse_string()?;
 
-                    let status_line = "HTTP/1.1 200 OK";
-                    let length = content_vector.len();
-                    
-                    let response = 
-                    format!("{status_line}\r\nContent-Type: {content_type}\r\nContent-Length: {length}\r\n\r\n");
+            //key and value are seperated by ':'
+            self.skip_whitespace();
+            self.expect(b':')?;
 
-                    request.stream.write_all(response.as_bytes()).unwrap();
-                    request.stream.write_all(&content_vector).unwrap();
-                },
-                Err(error_message) => {
-                    println!("{}", error_message); 
+            let value = self.parse_value()?;
+            object.insert(key, value);
+
+            //either the next pair follows after ',' or the object ends with '}'
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b'}') => {
+                    self.position += 1;
+                    break;
                 },
-            };
-        },
-        Err(error_message) => {
-            println!("file open error message{}", error_message); 
+                Some(_) => return Err(self.error("expected ',' or '}'")),
+                None => return Err(self.error("unexpected end of json")),
+            }
+        }
 
-            //check the error kind to respons accordingly
-            match error_message.kind() {
-                ErrorKind::NotFound => {
-                    println!("error kind is NotFound");
+        self.depth -= 1;
+        Ok(JsonType::Object(object))
+    }
 
-                    //TODO send 404 response
-                    send_404(request);
+    fn parse_array (&mut self) -> Result<JsonType, JsonError> {
+        self.enter_nesting()?;
+        self.expect(b'[')?;
 
+        let mut array = Vec::new();
+
+        //check for empty array
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.position += 1;
+            self.depth -= 1;
+            return Ok(JsonType::Array(array));
+        }
+
+        loop {
+            array.push(self.parse_value()?);
+
+            //either the next value follows after ',' or the array ends with ']'
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b']') => {
+                    self.position += 1;
+                    break;
                 },
-                _ => {
-                    println!("unkown error kind");
-                }
+                Some(_) => return Err(self.error("expected ',' or ']'")),
+                None => return Err(self.error("unexpected end of json")),
             }
-        },
-    };
-}
+        }
 
-pub fn execute_api_call(
-    request: HTTPRequest, 
-    database_connections: Arc<DatabaseConnectionPool>,
-    category: &str, 
-    function: &str
-) {
-
-    //check catgeory and function and execute accordingly
-    match category {
-        "login" => {
-            match function {
-                "logon" => api::login::api_login_logon(request, database_connections),
-                "auto_logon" => api::login::api_login_auto_logon(request, database_connections),
-                _ => send_404(request),
-            }
-        },
-        "auth" => {
-            match function {
-                "auth_user" => api::auth::api_auth_auth_user(request, database_connections),
-                _ => send_404(request),
-            }
-        },
-        _ => send_404(request),
+        self.depth -= 1;
+        Ok(JsonType::Array(array))
     }
-}
 
-pub fn generate_token() -> String {
-    rand::thread_rng()
-        .sample_iter(&Alphanumeric)
-        .take(32)
-        .map(char::from)
-        .collect()
-}
+    fn parse_number (&mut self) -> Result<JsonType, JsonError> {
+        let start = self.position;
+        let mut is_float = false;
+
+        //optional minus sign
+        if self.peek() == Some(b'-') {
+            self.position += 1;
+        }
 
-pub fn api_send_response_json <T: MatchJsonType> (
-    mut request: HTTPRequest, 
-    database_response: Vec<BTreeMap<String, Option<T>>>
-) {
-    //turn database data into json
-    let json = json_encode(&database_response);
+        //integer part, leading zeros are not allowed
+        match self.peek() {
+            Some(b'0') => self.position += 1,
+            Some(b'1'..=b'9') => self.skip_digits(),
+            _ => return Err(self.error("expected digit")),
+        }
 
-    //variable to hold the length of the json
-    let length = json.len();
+        //fraction part
+        if self.peek() == Some(b'.') {
+            is_float = true;
+            self.position += 1;
+            if !matches!(self.peek(), Some(b'0'..=b'9')) {
+                return Err(self.error("expected digit after decimal point"));
+            }
+            self.skip_digits();
+        }
 
-    //set statusline
-    let status_line = "HTTP/1.1 200 Ok";
+        //exponent part
+        if let Some(b'e' | b'E') = self.peek() {
+            is_float = true;
+            self.position += 1;
+            if let Some(b'+' | b'-') = self.peek() {
+                self.position += 1;
+            }
+            if !matches!(self.peek(), Some(b'0'..=b'9')) {
+                return Err(self.error("expected digit in exponent"));
+            }
+            self.skip_digits();
+        }
 
-    //format the response
-    let response = 
-    format!("{status_line}\r\nContent-Type: application/json\r\nContent-Length: {length}\r\n\r\n");
+        //the number only contains ascii characters
+        let number = std::str::from_utf8(&self.bytes[start..self.position]).unwrap();
 
-    //send the response, header and content
-    request.stream.write_all(response.as_bytes()).unwrap();
-    request.stream.write_all(&json.as_bytes()).unwrap();
-}
+        //use the smallest integer type the number fits into
+        if !is_float {
+            if let Ok(number) = number.parse::<i32>() {
+                return Ok(JsonType::Number(number));
+            }
+            if let Ok(number) = number.parse::<i64>() {
+                return Ok(JsonType::BigNumber(number));
+            }
+        }
 
-pub fn send_404 (mut request: HTTPRequest) {
+        match number.parse::<f64>() {
+            Ok(number) if number.is_finite() => Ok(JsonType::Float(number)),
+            _ => Err(JsonError { message: String::from("number out of range"), offset: start }),
+        }
+    }
 
-    let status_line = "HTTP/1.1 404 Not Found";
+    fn skip_digits (&mut self) {
+        while let Some(b'0'..=b'9') = self.peek() {
+            self.position += 1;
+        }
+    }
 
-    //create vector to hold content
-    let mut content_vector = Vec::new();
+    fn parse_string (&mut self) -> Result<String, JsonError> {
+        self.expect(b'"')?;
 
-    //open the file, handle errors
-    File::open("404.html").unwrap()
-        .read_to_end(&mut content_vector)
-        .unwrap();
+        let mut string: Vec<u8> = Vec::new();
 
-    let length = content_vector.len();
+        loop {
+            match self.peek() {
+                None => return Err(self.error("unterminated string")),
+                Some(b'"') => {
+                    self.position += 1;
+                    break;
+                },
+                Some(b'\\') => {
+                    self.position += 1;
+                    let escaped = match self.peek() {
+                        Some(b'u') => {
+                            self.position += 1;
+                            self.parse_unicode_escape()?
+                        },
+                        Some(byte) => {
+                            let escaped = match byte {
+                                b'"' => '"',
+                                b'\\' => '\\',
+                                b'/' => '/',
+                                b'b' => '\u{08}',
+                                b'f' => '\u{0c}',
+                                b'n' => '\n',
+                                b'r' => '\r',
+                                b't' => '\t',
+                                _ => return Err(self.error("invalid escape sequence")),
+                            };
+                            self.position += 1;
+                            escaped
+                        },
+                        None => return Err(self.error("unterminated string")),
+                    };
 
-    let response = 
-    format!("{status_line}\r\nContent-Length: {length}\r\n\r\n");
-    request.stream.write_all(response.as_bytes()).unwrap();
-    request.stream.write_all(&content_vector).unwrap();
-}
+                    let mut buffer = [0; 4];
+                    string.extend_from_slice(escaped.encode_utf8(&mut buffer).as_bytes());
+                },
+                //control characters have to be escaped
+                Some(0x00..=0x1f) => return Err(self.error("control character in string")),
+                Some(byte) => {
+                    //multi byte utf8 characters are copied byte by byte
+                    string.push(byte);
+                    self.position += 1;
+                },
+            }
+        }
 
-pub fn get_content_type (file_type: &str) -> &str {
-    match file_type {
-        "png" => "image/png",
-        "ico" => "image/x-icon",
-        "css" => "text/css",
-        "js" => "application/javascript",
-        _ => "*/*"
+        //the input is a str and escapes are encoded as utf8, so the bytes are valid utf8
+        Ok(String::from_utf8(string).unwrap())
     }
-}
 
-pub fn parse_json_string (json_string: &str) -> HashMap<String, JsonType> {
+    //read the 4 hex digits after '\u'
+    fn parse_hex_digits (&mut self) -> Result<u32, JsonError> {
+        let hex = match self.bytes.get(self.position..self.position + 4) {
+            Some(hex) => hex,
+            None => return Err(self.error("unexpected end of unicode escape")),
+        };
 
-    let mut json_hash = HashMap::new();
+        let hex = std::str::from_utf8(hex).map_err(|_| self.error("invalid unicode escape"))?;
 
-    //remove '{' and '}' from beginning and end
-    let json_trim: &str = &json_string[1..json_string.len() - 1];
+        match u32::from_str_radix(hex, 16) {
+            Ok(code) if hex.bytes().all(|byte| byte.is_ascii_hexdigit()) => {
+                self.position += 4;
+                Ok(code)
+            },
+            _ => Err(self.error("invalid unicode escape")),
+        }
+    }
 
-    for item in json_trim.split(",") {//split by the value pairs
-        let mut item_iter = item.split(":");//split the key and the value
+    fn parse_unicode_escape (&mut self) -> Result<char, JsonError> {
+        let start = self.position;
+        let code = self.parse_hex_digits()?;
 
-        let key = item_iter.next().unwrap();//get the key
-        let key_trim: &str = &key[1..key.len() - 1];//remove the ""
+        let code = match code {
+            //high surrogate, has to be followed by an escaped low surrogate
+            0xd800..=0xdbff => {
+                if !self.bytes[self.position..].starts_with(b"\\u") {
+                    return Err(JsonError { message: String::from("unpaired surrogate"), offset: start });
+                }
+                self.position += 2;
 
-        let value_raw = item_iter.next().unwrap();
-        //check if the value starts and ends with ", then it is a string
-        let value = if value_raw.starts_with('"') && value_raw.ends_with('"') {
-            JsonType::String(value_raw[1..value_raw.len() - 1].to_string())
-        } else if value_raw == "true" {
-            JsonType::Boolean(true)
-        } else if value_raw == "false" {
-            JsonType::Boolean(false)
-        } else if value_raw == "null" {
-            JsonType::Null
-        } else if let Ok(num) = value_raw.parse::<i32>() {
-            JsonType::Number(num)
-        } else {
-            continue;
-        };
+                let low = self.parse_hex_digits()?;
+                if !(0xdc00..=0xdfff).contains(&low) {
+                    return Err(JsonError { message: String::from("invalid low surrogate"), offset: start });
+                }
 
-            json_hash.insert(key_trim.to_string(), value);
+                0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00)
+            },
+            //low surrogate without high surrogate
+            0xdc00..=0xdfff => {
+                return Err(JsonError { message: String::from("unpaired surrogate"), offset: start });
+            },
+            _ => code,
         };
 
-    json_hash
+        Ok(char::from_u32(code).unwrap())
+    }
+}
+
+
+//parameters of the query string, a key can appear multiple times
+#[derive(Debug, Default)]
+pub struct QueryString {
+    parameters: HashMap<String, Vec<String>>,
 }
 
+impl QueryString {
+    //first value of the key
+    pub fn get (&self, key: &str) -> Option<&str> {
+        self.parameters.get(key).and_then(|values| values.first()).map(|value| value.as_str())
+    }
+
+    //all values of the key in the order of the query string
+    pub fn get_all (&self, key: &str) -> &[String] {
+        match self.parameters.get(key) {
+            Some(values) => values,
+            None => &[],
+        }
+    }
+
+    pub fn contains_key (&self, key: &str) -> bool {
+        self.parameters.contains_key(key)
+    }
+}
 
-pub fn convert_query_string (query_string: String) -> HashMap::<String, String> {
-    let mut query_string_hashmap = HashMap::new();
+pub fn convert_query_string (query_string: &str) -> QueryString {
+    let mut query_string_struc = QueryString::default();
 
     for data_pair in query_string.split('&') {
 
-        let mut data_pair_split = data_pair.split('=');
+        //skip empty pairs, for example from '&&' or a trailing '&'
+        if data_pair.is_empty() {
+            continue;
+        }
+
+        //a key without '=' has an empty value, for example '?flag'
+        let (variable, value) = match data_pair.split_once('=') {
+            Some((variable, value)) => (variable, value),
+            None => (data_pair, ""),
+        };
 
-        let variable = data_pair_split.next().unwrap().to_string();
-        let value = data_pair_split.next().unwrap().to_string();
+        //'+' stands for a space in query strings
+        let variable = percent_decode_string(&variable.replace('+', " "));
+        let value = percent_decode_string(&value.replace('+', " "));
 
-        query_string_hashmap.insert(variable, value);
+        query_string_struc.parameters.entry(variable).or_default().push(value);
 
     }
 
 
-    return query_string_hashmap;
+    query_string_struc
 }
 
-pub fn json_encode<T: MatchJsonType  > (data: &Vec<BTreeMap<String, Option<T>>>) -> String {
-    let mut json = String::new();
-
-    //number of rows in the query
-    let number_of_rows = data.len();
+//decode '%XX' sequences into a string, invalid utf8 is replaced
+pub fn percent_decode_string (string: &str) -> String {
+    String::from_utf8_lossy(&percent_decode(string)).to_string()
+}
 
-    //variable to count the number of  rows
-    let mut row_number = 1;
+//decode '%XX' sequences into bytes
+//invalid sequences are kept as they are
+pub fn percent_decode (string: &str) -> Vec<u8> {
+    let bytes = string.as_bytes();
+    let mut decoded: Vec<u8> = Vec::with_capacity(bytes.len());
+
+    let mut position = 0;
+    while position < bytes.len() {
+        if bytes[position] == b'%' {
+            //get the two hex digits after the '%'
+            let hex = bytes
+                .get(position + 1..position + 3)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .filter(|hex| hex.bytes().all(|byte| byte.is_ascii_hexdigit()));
+
+            if let Some(hex) = hex {
+                decoded.push(u8::from_str_radix(hex, 16).unwrap());
+                position += 3;
+                continue;
+            }
+        }
 
-    //if there are multiple rows
-    if number_of_rows > 1 {
-        json.push_str("[");
+        decoded.push(bytes[position]);
+        position += 1;
     }
 
-    //loop through rows
-    for row in data {
-
-        //add '{' to string for row start
-        json.push('{');
+    decoded
+}
 
-        //number of keys in the row
-        let number_of_keys = row.keys().len();
+pub fn json_encode<T: MatchJsonType> (data: &T) -> String {
+    let mut json = String::new();
 
-        //variable to count the keys
-        let mut key_number = 1;
+    json_encode_value(&data.match_json_type(), &mut json);
 
-        for key in row.keys() {
+    json
+}
 
-            json.push('"');
-            json.push_str(key);
-            json.push('"');
-            json.push_str(": ");
+fn json_encode_value (value: &JsonType, json: &mut String) {
+    match value {
+        JsonType::String(string) => {
+            json_encode_string(string, json);
+        },
+        JsonType::Number(number) => {
+            json.push_str(&number.to_string());
+        },
+        JsonType::BigNumber(number) => {
+            json.push_str(&number.to_string());
+        },
+        JsonType::Float(number) => {
+            //json has no representation for nan and infinity
+            if number.is_finite() {
+                json.push_str(&number.to_string());
+            } else {
+                json.push_str("null");
+            }
+        },
+        JsonType::Boolean(bool) => {
+            json.push_str(&bool.to_string());
+        },
+        JsonType::Null => {
+            json.push_str("null");
+        },
+        JsonType::Array(array) => {
+            json.push('[');
 
-            match row.get(key).unwrap() {
-                None => {
-                    json.push_str("null");
-                },
-                Some(value) => {
-                    match value.match_json_type() {
-                        JsonType::String(string) => {
-                            json.push('"');
-                            json.push_str(&string);
-                            json.push('"');
-                        },
-                        JsonType::Number(number) => {
-                            json.push_str(&number.to_string());
-                        },
-                        JsonType::BigNumber(number) => {
-                            json.push_str(&number.to_string());
-                        },
-                        JsonType::Boolean(bool) => {
-                            json.push_str(&bool.to_string());
-                        },
-                        JsonType::Null => {
-                            json.push_str("null");
-                        },
+            for (index, item) in array.iter().enumerate() {
+                //seperate the values
+                if index > 0 {
+                    json.push_str(", ");
+                }
 
-                    }
-                },
+                json_encode_value(item, json);
             }
 
-            //check, if there are still keys comming
-            if key_number < number_of_keys {
-                json.push_str(", ");
-            }
+            json.push(']');
+        },
+        JsonType::Object(object) => {
+            json.push('{');
 
-            key_number += 1;
+            for (index, (key, item)) in object.iter().enumerate() {
+                //seperate the key value pairs
+                if index > 0 {
+                    json.push_str(", ");
+                }
 
-        }
+                json_encode_string(key, json);
+                json.push_str(": ");
+                json_encode_value(item, json);
+            }
 
-        //add '}' to string for row end
-        json.push('}');
+            json.push('}');
+        },
+    }
+}
 
-        //check, if there are still rows comming
-        if row_number < number_of_rows {
-            json.push_str(", ");
+//write a string between quotes, escaping quotes, backslashes and control characters
+fn json_encode_string (string: &str, json: &mut String) {
+    json.push('"');
+
+    for character in string.chars() {
+        match character {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            '\u{08}' => json.push_str("\\b"),
+            '\u{0c}' => json.push_str("\\f"),
+            //remaining control characters as unicode escape
+            '\u{00}'..='\u{1f}' => json.push_str(&format!("\\u{:04x}", character as u32)),
+            _ => json.push(character),
         }
-
-        row_number += 1;
-    }
-    
-    //if there are multiple rows
-    if number_of_rows > 1 {
-        json.push_str("]");
     }
 
-    json
+    json.push('"');
 }
 
 pub struct ThreadPool {
     workers: Vec<Worker>,
     sender: Option<mpsc::Sender<Job>>,
+    load: PoolLoad,
+}
+
+//number of jobs, which are queued or running in a thread pool
+#[derive(Clone)]
+pub struct PoolLoad {
+    jobs: Arc<AtomicUsize>,
+    size: usize,
+}
 
+impl PoolLoad {
+    //true, if no worker is free for a new job
+    pub fn saturated (&self) -> bool {
+        self.jobs.load(Ordering::SeqCst) >= self.size
+    }
 }
 
 impl ThreadPool {
@@ -629,13 +3589,20 @@ impl ThreadPool {
 
         let receiver = Arc::new(Mutex::new(receiver));
 
+        let load = PoolLoad { jobs: Arc::new(AtomicUsize::new(0)), size };
+
         let mut workers = Vec::with_capacity(size);
 
         for id in 0..size {
-            workers.push(Worker::new(id, Arc::clone(&receiver)));
+            workers.push(Worker::new(id, Arc::clone(&receiver), Arc::clone(&load.jobs)));
         }
 
-        ThreadPool { workers: workers, sender: Some(sender) }
+        ThreadPool { workers, sender: Some(sender), load }
+    }
+
+    //handle to the load of the pool, which can be checked from inside a job
+    pub fn load (&self) -> PoolLoad {
+        self.load.clone()
     }
 
     pub fn execute<F>(&self, f: F)
@@ -644,6 +3611,9 @@ impl ThreadPool {
     {
         let job = Box::new(f);
 
+        //counted until the worker has finished the job
+        self.load.jobs.fetch_add(1, Ordering::SeqCst);
+
         self.sender.as_ref().unwrap().send(job).unwrap();
     }
 }
@@ -669,7 +3639,7 @@ pub struct Worker {
 }
 
 impl Worker {
-    pub fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Job>>>) -> Worker {
+    pub fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Job>>>, jobs: Arc<AtomicUsize>) -> Worker {
 
         let thread = thread::spawn(move || loop {
             let message = receiver.lock().unwrap().recv();
@@ -677,7 +3647,12 @@ impl Worker {
             match message {
                 Ok(job) => { 
                     println!("Worker {id} got a job, executing.");
-                    job();
+
+                    //a panicking job must neither end the worker nor keep counting as running
+                    if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+                        println!("Worker {id} job panicked");
+                    }
+                    jobs.fetch_sub(1, Ordering::SeqCst);
                 }
                 Err(_) => {
                     println!("Worker {id} disconnected");
@@ -697,19 +3672,28 @@ type Job = Box<dyn FnOnce() + Send + 'static>;
 pub struct DatabaseConnectionPool {
     connections: Arc<Mutex<VecDeque<DatabaseConnection>>>,
     condvar: Arc<Condvar>,
-
+    //shared with the connections, so a new handler reaches connections which are in use as well
+    notice_handler: NoticeHandler,
 }
 
+//function which receives the notices of a connection, read before every query
+type NoticeHandler = Arc<RwLock<fn(&DatabaseNotice)>>;
+
 impl DatabaseConnectionPool {
     pub fn new (size: usize, ip: &str, port: u16, user: &str, password: &str, database: &str) -> DatabaseConnectionPool {
+        let notice_handler: NoticeHandler = Arc::new(RwLock::new(log_database_notice));
+
         let mut connections = VecDeque::new();
         for i in 0..size {
-            connections.push_back(DatabaseConnection::new(ip, port, user, password, database, i));
+            let mut connection = DatabaseConnection::new(ip, port, user, password, database, i);
+            connection.notice_handler = Arc::clone(&notice_handler);
+            connections.push_back(connection);
         }
         
         DatabaseConnectionPool{ 
             connections: Arc::new(Mutex::new(connections)),
-            condvar: Arc::new(Condvar::new())
+            condvar: Arc::new(Condvar::new()),
+            notice_handler,
         }
     }
 
@@ -740,11 +3724,18 @@ impl DatabaseConnectionPool {
         self.condvar.notify_one();
     }
 
+    //set the function which receives the notices of every connection in the pool
+    //connections which are currently in use get the handler with their next query
+    pub fn set_notice_handler (&self, notice_handler: fn(&DatabaseNotice)) {
+        *self.notice_handler.write().unwrap() = notice_handler;
+    }
+
 }
 
 pub struct DatabaseConnection {
     id: usize,
     reader: BufReader<TcpStream>,
+    notice_handler: NoticeHandler,
 }
 
 impl DatabaseConnection {
@@ -754,42 +3745,64 @@ impl DatabaseConnection {
         let stream = TcpStream::connect((ip, port)).unwrap(); 
         let mut reader = BufReader::new(stream);
 
-        Self::send_startup(&mut reader, &user, &database);
-        Self::read_authentication_method(&mut reader);
-        Self::send_password(&mut reader, password);
-        Self::read_authentication_response(&mut reader);
+        Self::send_startup(&mut reader, user, database);
+        Self::authenticate(&mut reader, user, password);
         Self::read_paramters(&mut reader);
 
         //debug
         println!("Databaseconnection {} established", id);
 
-        DatabaseConnection { id, reader } 
+        DatabaseConnection { id, reader, notice_handler: Arc::new(RwLock::new(log_database_notice)) } 
     }
     
-    pub fn query(&mut self, query: &str) -> Vec<BTreeMap<String, Option<DatabaseValue>>> {
+    pub fn query(&mut self, query: &str) -> Result<Vec<DatabaseRow>, DatabaseError> {
         //debug
         println!("Databse connection {} got query: {}", self.id, query);
         
         //send query to database
-        Self::send_query(&mut self.reader, &query);
+        Self::send_query(&mut self.reader, query);
         //put response of database into variable
-        let data = Self::read_query_response(&mut self.reader);
+        let notice_handler = *self.notice_handler.read().unwrap();
+        let data = Self::read_query_response(&mut self.reader, notice_handler);
 
         //return the variable
-        return data;
+        data
+    }
+
+    //run a query with bind parameters over the extended query protocol
+    //parameters are referenced in the query as $1, $2, ...
+    pub fn query_params(&mut self, query: &str, params: &[&dyn ToSql]) -> Result<Vec<DatabaseRow>, DatabaseError> {
+        //debug
+        println!("Databse connection {} got query with {} parameters: {}", self.id, params.len(), query);
+
+        //create one message holding parse, bind, describe, execute and sync
+        //so the whole query is sent to the database at once
+        let mut message: Vec<u8> = vec![];
+        Self::add_parse_message(query, params, &mut message);
+        Self::add_bind_message(params, &mut message);
+        Self::add_describe_message(&mut message);
+        Self::add_execute_message(&mut message);
+        Self::add_sync_message(&mut message);
+
+        //send messages to database
+        Self::write_to_db_stream(self.reader.get_mut(), &message);
+
+        //put response of database into variable
+        let notice_handler = *self.notice_handler.read().unwrap();
+        Self::read_extended_query_response(&mut self.reader, notice_handler)
     }
     
 
     //---private
     //write to database stream
-    fn write_to_db_stream (stream: &mut TcpStream, message: &Vec<u8>) {
+    fn write_to_db_stream (stream: &mut TcpStream, message: &[u8]) {
         stream.write_all(message).unwrap();
             
     }
 
     //read from database stream
     //read exact into vector
-    fn read_from_db_stream (reader: &mut BufReader<TcpStream>, response_vector: &mut Vec<u8>) {
+    fn read_from_db_stream (reader: &mut BufReader<TcpStream>, response_vector: &mut [u8]) {
         reader.read_exact(response_vector).unwrap(); 
     }
 
@@ -879,27 +3892,176 @@ impl DatabaseConnection {
 
     }
     
-    fn read_authentication_method (reader: &mut BufReader<TcpStream>) {
+    fn authenticate (reader: &mut BufReader<TcpStream>, user: &str, password: &str) {
+        //the database tells which authentication method it wants to use
+        let (auth_method, auth_data) = Self::read_authentication_method(reader);
+
+        match auth_method {
+            //0 = no authentication needed, connection is already accepted
+            0 => return,
+            //3 = plain text password
+            3 => Self::send_password(reader, password),
+            //5 = md5 hashed password, the data holds the 4 bytes salt
+            5 => Self::send_password(reader, &Self::md5_password(user, password, &auth_data)),
+            //10 = sasl, the data holds the list of supported mechanisms
+            10 => Self::authenticate_scram_sha_256(reader, password, &auth_data),
+            _ => panic!("authentication method {} is not supported", auth_method),
+        }
+
+        Self::read_authentication_response(reader);
+    }
+
+    fn read_authentication_method (reader: &mut BufReader<TcpStream>) -> (i32, Vec<u8>) {
         //create vector to hold initial ascii char 1byte of reply and content length 4bytes
-        let mut auth_response_head: Vec<u8> = vec![0; 9];
+        let mut auth_response_head: Vec<u8> = vec![0; 5];
         
         Self::read_from_db_stream(reader, &mut auth_response_head);
 
         //turn ascii packet identifier into char
         let packet_identifier = char::from_u32(auth_response_head[0] as u32).unwrap();
 
-        //make sure, that the response of the db matches an authentication request
-        assert_eq!(packet_identifier, 'R', "response does not match authentication request");
+        //the database rejected the connection, for example because of a wrong password
+        if packet_identifier == 'E' {
+            let error_length: i32 = i32::from_be_bytes(auth_response_head[1..5].try_into().unwrap());
+            panic!("database rejected authentication: {}", Self::read_error(reader, error_length));
+        }
+
+        //make sure, that the response of the db matches an authentication request
+        assert_eq!(packet_identifier, 'R', "response does not match authentication request");
+
+        //read the inital packet an retrieve the individual bytes of the content length
+        let db_response_length: i32 = i32::from_be_bytes(auth_response_head[1..5].try_into().unwrap());
+        assert!(db_response_length >= 8, "length should be at least 8, 4 bytes length, 4 bytes int authm");
+
+        //read the rest of the message, 4 bytes authentication method followed by method specific data
+        let mut auth_response_body: Vec<u8> = vec![0; db_response_length as usize - 4];
+        Self::read_from_db_stream(reader, &mut auth_response_body);
+
+        //read authentication method
+        let auth_method: i32 = i32::from_be_bytes(auth_response_body[..4].try_into().unwrap());
+
+        (auth_method, auth_response_body[4..].to_vec())
+    }
+
+    //md5 password is 'md5' followed by md5(md5(password + user) + salt) as hex
+    fn md5_password (user: &str, password: &str, salt: &[u8]) -> String {
+        let mut hasher = Md5::new();
+        hasher.update(password.as_bytes());
+        hasher.update(user.as_bytes());
+        let inner_hash = Self::bytes_to_hex(&hasher.finalize());
+
+        let mut hasher = Md5::new();
+        hasher.update(inner_hash.as_bytes());
+        hasher.update(salt);
+        let outer_hash = Self::bytes_to_hex(&hasher.finalize());
+
+        format!("md5{}", outer_hash)
+    }
 
-        //read the inital packet an retrieve the individual bytes of the content length
-        let db_response_length: i32 = i32::from_be_bytes(auth_response_head[1..5].try_into().unwrap());
-        assert_eq!(db_response_length, 8, "length should be 8, 4 bytes length, 4 bytes int authm");
+    fn bytes_to_hex (bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    fn hmac_sha_256 (key: &[u8], message: &[u8]) -> Vec<u8> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+        mac.update(message);
+        mac.finalize().into_bytes().to_vec()
+    }
 
-        //read authentication method, 3 equals plain text password
-        let auth_method: i32 = i32::from_be_bytes(auth_response_head[5..].try_into().unwrap());
+    fn authenticate_scram_sha_256 (reader: &mut BufReader<TcpStream>, password: &str, mechanisms: &[u8]) {
+        //the mechanisms are a list of null terminated strings, ending with an empty string
+        let scram_available = mechanisms
+            .split(|byte| *byte == 0x00)
+            .any(|mechanism| mechanism == b"SCRAM-SHA-256");
+
+        assert!(scram_available, "database does not offer SCRAM-SHA-256");
+
+        //client nonce, printable characters without ','
+        let client_nonce: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(24)
+            .map(char::from)
+            .collect();
+
+        //the user name is taken from the startup message, so it is left empty here
+        //'n,,' means the client does not support channel binding
+        let client_first_bare = format!("n=,r={}", client_nonce);
+        let client_first = format!("n,,{}", client_first_bare);
+
+        //send the sasl initial response with the name of the mechanism
+        let mut initial_response_body: Vec<u8> = vec![];
+        Self::add_str_as_bytes_to_vec("SCRAM-SHA-256", &mut initial_response_body);
+        initial_response_body.push(0x00);
+        Self::add_i32_as_be_bytes_to_vec(&(client_first.len() as i32), &mut initial_response_body);
+        Self::add_str_as_bytes_to_vec(&client_first, &mut initial_response_body);
+
+        let mut initial_response: Vec<u8> = vec![];
+        Self::add_message_to_vec(b'p', &initial_response_body, &mut initial_response);
+        Self::write_to_db_stream(reader.get_mut(), &initial_response);
+
+        //11 = sasl continue, holds the server first message
+        let (auth_method, server_first) = Self::read_authentication_method(reader);
+        assert_eq!(auth_method, 11, "expected sasl continue from database");
+        let server_first = String::from_utf8(server_first).unwrap();
+
+        //server first message looks like 'r=<nonce>,s=<salt>,i=<iterations>'
+        let mut server_nonce = "";
+        let mut salt: Vec<u8> = vec![];
+        let mut iterations: u32 = 0;
+
+        for attribute in server_first.split(',') {
+            if let Some(value) = attribute.strip_prefix("r=") {
+                server_nonce = value;
+            } else if let Some(value) = attribute.strip_prefix("s=") {
+                salt = BASE64.decode(value).unwrap();
+            } else if let Some(value) = attribute.strip_prefix("i=") {
+                iterations = value.parse().unwrap();
+            }
+        }
 
-        //in this case, its always 3, plain text password
-        assert_eq!(auth_method, 3, "authentication method must be plain password");
+        //the server nonce has to start with the nonce of the client
+        assert!(server_nonce.starts_with(&client_nonce), "server nonce does not match client nonce");
+
+        //'biws' is base64 of 'n,,'
+        let client_final_without_proof = format!("c=biws,r={}", server_nonce);
+        let auth_message = format!("{},{},{}", client_first_bare, server_first, client_final_without_proof);
+
+        //derive the keys from the password
+        let mut salted_password = [0u8; 32];
+        pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), &salt, iterations, &mut salted_password);
+
+        let client_key = Self::hmac_sha_256(&salted_password, b"Client Key");
+        let stored_key = Sha256::digest(&client_key);
+        let client_signature = Self::hmac_sha_256(&stored_key, auth_message.as_bytes());
+
+        //proof is client key xor client signature
+        let client_proof: Vec<u8> = client_key
+            .iter()
+            .zip(client_signature.iter())
+            .map(|(key, signature)| key ^ signature)
+            .collect();
+
+        let client_final = format!("{},p={}", client_final_without_proof, BASE64.encode(&client_proof));
+
+        //send the sasl response
+        let mut final_response: Vec<u8> = vec![];
+        Self::add_message_to_vec(b'p', client_final.as_bytes(), &mut final_response);
+        Self::write_to_db_stream(reader.get_mut(), &final_response);
+
+        //12 = sasl final, holds the signature of the server
+        let (auth_method, server_final) = Self::read_authentication_method(reader);
+        assert_eq!(auth_method, 12, "expected sasl final from database");
+        let server_final = String::from_utf8(server_final).unwrap();
+
+        //verify that the server also knows the password
+        let server_key = Self::hmac_sha_256(&salted_password, b"Server Key");
+        let server_signature = Self::hmac_sha_256(&server_key, auth_message.as_bytes());
+
+        assert_eq!(
+            server_final.trim_end_matches('\0'),
+            format!("v={}", BASE64.encode(&server_signature)),
+            "server signature does not match"
+        );
     }
 
     fn send_password (reader: &mut BufReader<TcpStream>, password: &str) {
@@ -935,17 +4097,8 @@ impl DatabaseConnection {
     
     fn read_authentication_response (reader: &mut BufReader<TcpStream>) {
         
-        //create vector to read response
-        //total resonse length should be 9
-        //1 byte identifyer, 4 bytes length, 4 bythes response
-        let mut auth_response_head: Vec<u8> = vec![0; 9];
-        //read from stream into initial response
-        Self::read_from_db_stream(reader, &mut auth_response_head);
-
-        //check if auth response is OK
-        //loop through the auth response body and extract the auth code
-        //array holding the bytes of the authentication result
-        let auth_response: i32 = i32::from_be_bytes(auth_response_head[5..].try_into().unwrap());
+        //read the response of the database after the password has been sent
+        let (auth_response, _) = Self::read_authentication_method(reader);
 
         //panic if connection failed
         assert_eq!(auth_response, 0, "authentication has not been accpeted");
@@ -957,12 +4110,18 @@ impl DatabaseConnection {
             let mut response: Vec<u8> = vec![0; 5];
             Self::read_from_db_stream(reader, &mut response);
 
-            //check if there are incoming unexpeted messge types, should be either S, K or Z
-            assert!(matches!(response[0], 83 | 75 | 90));
+            //check if there are incoming unexpeted messge types, should be either S, K, N or Z
+            assert!(matches!(response[0], 83 | 75 | 78 | 90));
 
             //extract response length
             let response_length: i32 = i32::from_be_bytes(response[1..].try_into().unwrap());
 
+            //78 equals N, a notice during the startup
+            if response[0] == 78 {
+                log_database_notice(&Self::read_error(reader, response_length));
+                continue;
+            }
+
             let mut body: Vec<u8> = vec![0; response_length as usize - 4];
             Self::read_from_db_stream(reader, &mut body);
 
@@ -987,7 +4146,7 @@ impl DatabaseConnection {
         Self::add_i32_as_be_bytes_to_vec(&query_length, &mut query_vec); 
 
         //add query to vector as bytes
-        Self::add_str_as_bytes_to_vec(&query, &mut query_vec);
+        Self::add_str_as_bytes_to_vec(query, &mut query_vec);
 
         //add null terminator after user string
         query_vec.push(0x00);
@@ -997,34 +4156,189 @@ impl DatabaseConnection {
 
     }
 
-    fn read_query_response (reader: &mut BufReader<TcpStream>) -> Vec<BTreeMap<String, Option<DatabaseValue>>> {
+    //add a message with a char tag and a body to the message vector
+    //the length of the message is calculated from the body
+    fn add_message_to_vec (tag: u8, body: &[u8], vector: &mut Vec<u8>) {
+        //add char tag
+        vector.push(tag);
+
+        //length includes the 4 bytes of the length itself
+        let length: i32 = 4 + body.len() as i32;
+        Self::add_i32_as_be_bytes_to_vec(&length, vector);
+
+        vector.extend_from_slice(body);
+    }
+
+    fn add_parse_message (query: &str, params: &[&dyn ToSql], message: &mut Vec<u8>) {
+        let mut body: Vec<u8> = vec![];
+
+        //name of the prepared statement, empty string for the unnamed statement
+        body.push(0x00);
+
+        //add query and null terminator
+        Self::add_str_as_bytes_to_vec(query, &mut body);
+        body.push(0x00);
+
+        //add the number of parameter types and the type oid of every parameter
+        Self::add_i16_as_be_bytes_to_vec(&(params.len() as i16), &mut body);
+        for param in params {
+            Self::add_i32_as_be_bytes_to_vec(&param.type_oid(), &mut body);
+        }
+
+        Self::add_message_to_vec(b'P', &body, message);
+    }
+
+    fn add_bind_message (params: &[&dyn ToSql], message: &mut Vec<u8>) {
+        let mut body: Vec<u8> = vec![];
+
+        //name of the portal and of the prepared statement, both unnamed
+        body.push(0x00);
+        body.push(0x00);
+
+        //0 parameter format codes, all parameters are sent as text
+        Self::add_i16_as_be_bytes_to_vec(&0, &mut body);
+
+        //add number of parameters followed by length and value of every parameter
+        Self::add_i16_as_be_bytes_to_vec(&(params.len() as i16), &mut body);
+        for param in params {
+            match param.to_sql_text() {
+                Some(value) => {
+                    Self::add_i32_as_be_bytes_to_vec(&(value.len() as i32), &mut body);
+                    Self::add_str_as_bytes_to_vec(&value, &mut body);
+                },
+                //null value is sent with length -1 and no value bytes
+                None => Self::add_i32_as_be_bytes_to_vec(&-1, &mut body),
+            }
+        }
+
+        //0 result format codes, all results are returned as text
+        Self::add_i16_as_be_bytes_to_vec(&0, &mut body);
+
+        Self::add_message_to_vec(b'B', &body, message);
+    }
+
+    fn add_describe_message (message: &mut Vec<u8>) {
+        //describe the unnamed portal, 'P' for portal
+        Self::add_message_to_vec(b'D', &[b'P', 0x00], message);
+    }
+
+    fn add_execute_message (message: &mut Vec<u8>) {
+        let mut body: Vec<u8> = vec![];
+
+        //unnamed portal
+        body.push(0x00);
+
+        //maximum number of rows to return, 0 for no limit
+        Self::add_i32_as_be_bytes_to_vec(&0, &mut body);
+
+        Self::add_message_to_vec(b'E', &body, message);
+    }
+
+    fn add_sync_message (message: &mut Vec<u8>) {
+        Self::add_message_to_vec(b'S', &[], message);
+    }
+
+    fn read_extended_query_response (
+        reader: &mut BufReader<TcpStream>, 
+        notice_handler: fn(&DatabaseNotice)
+    ) -> Result<Vec<DatabaseRow>, DatabaseError> {
+
+        //create vector holding the individual rows
+        let mut rows: Vec<DatabaseRow> = Vec::new();
+
+        //variable holding a possible error of the query
+        let mut error: Option<DatabaseError> = None;
+
+        loop {
+            //read response head
+            let (response_type, response_length) = Self::read_message_head(reader, notice_handler);
+
+            //49 '1' is parse complete
+            //50 '2' is bind complete
+            //110 'n' is no data
+            //84 'T' is row description
+            //69 'E' is error
+            assert!(matches!(response_type, 49 | 50 | 110 | 84 | 69));
+
+            match response_type {
+                //parse and bind complete have no body
+                49 | 50 => {},
+                //if error read error
+                //after an error the database skips everything until the sync message
+                69 => {
+                    error = Some(Self::read_error(reader, response_length));
+                    break;
+                },
+                //the query does not return rows, for example update or insert
+                110 => {
+                    //read the command complete message
+                    let (complete_type, complete_length) = Self::read_message_head(reader, notice_handler);
+
+                    match complete_type {
+                        67 => {
+                            let complete_command = Self::read_complete_command(reader, complete_length);
+
+                            let mut complete_command_result = BTreeMap::new();
+
+                            complete_command_result.insert(
+                                String::from("Complete"), 
+                                Some(DatabaseValue::Varchar(complete_command))
+                            );
+
+                            rows.push(complete_command_result);
+                        },
+                        69 => error = Some(Self::read_error(reader, complete_length)),
+                        _ => println!("query empty"),
+                    }
+                    break;
+                },
+                //row description, followed by the data rows
+                _ => {
+                    let row_descriptions = Self::read_row_description(reader, response_length);
+                    if let Err(row_error) = Self::read_rows(reader, row_descriptions, &mut rows, notice_handler) {
+                        error = Some(row_error);
+                    }
+                    break;
+                },
+            }
+        }
+
+        //after the sync message, the database is ready for a new query
+        Self::read_ready_command(reader, notice_handler);
+
+        match error {
+            Some(error) => Err(error),
+            None => Ok(rows),
+        }
+    }
 
+    fn read_query_response (
+        reader: &mut BufReader<TcpStream>, 
+        notice_handler: fn(&DatabaseNotice)
+    ) -> Result<Vec<DatabaseRow>, DatabaseError> {
 
         //read response head
-        let mut query_response_head: Vec<u8> = vec![0; 5];
-        Self::read_from_db_stream(reader, &mut query_response_head);
-        //get the length of the message
-        let query_response_length: i32 = i32::from_be_bytes(query_response_head[1..].try_into().unwrap());
+        let (query_response_type, query_response_length) = Self::read_message_head(reader, notice_handler);
 
         //make sure, that the response is a row desciption
         //or error
-        assert!(matches!(query_response_head[0], 67 | 69 | 84));
+        assert!(matches!(query_response_type, 67 | 69 | 84));
 
         //create vector holding the individual rows
-        let mut rows: Vec<BTreeMap<String, Option<DatabaseValue>>> = Vec::new();
+        let mut rows: Vec<DatabaseRow> = Vec::new();
 
         //if error read error
         //and exit out of function
-        if query_response_head[0] == 69 {
+        if query_response_type == 69 {
             //if the response is an error, read the error
-            Self::read_error(reader, query_response_length);
+            let error = Self::read_error(reader, query_response_length);
             //after reading the error, check if the db is ready for a new query
-            Self::read_ready_command(reader);
-            return rows;
+            Self::read_ready_command(reader, notice_handler);
+            return Err(error);
         }
 
         //if response is query complete because of insert for exmaple
-        if query_response_head[0] == 67 {
+        if query_response_type == 67 {
             //if the response is complete, read the complte string
             let complete_command = Self::read_complete_command(reader, query_response_length);
 
@@ -1038,12 +4352,47 @@ impl DatabaseConnection {
             rows.push(complete_command_result);
 
             //after reading the error, check if the db is ready for a new query
-            Self::read_ready_command(reader);
-            return rows;
+            Self::read_ready_command(reader, notice_handler);
+            return Ok(rows);
+        }
+
+        //read the field descriptions of the returned rows
+        let row_descriptions = Self::read_row_description(reader, query_response_length);
+
+        //read next message, its either command complete, a datarow or empty query
+        let result = Self::read_rows(reader, row_descriptions, &mut rows, notice_handler);
+
+        //after successfull query, read the ready for new query command
+        Self::read_ready_command(reader, notice_handler);
+
+        result.map(|_| rows)
+
+    }
+
+    //read the type and the length of the next message
+    //notices can be sent by the database at any time, they are passed to the notice handler
+    fn read_message_head (reader: &mut BufReader<TcpStream>, notice_handler: fn(&DatabaseNotice)) -> (u8, i32) {
+        loop {
+            //1 byte identifyer, 4 bytes message length
+            let mut message_head: Vec<u8> = vec![0; 5];
+            Self::read_from_db_stream(reader, &mut message_head);
+            let message_length: i32 = i32::from_be_bytes(message_head[1..].try_into().unwrap());
+
+            //78 'N' is notice
+            if message_head[0] == 78 {
+                let notice = Self::read_error(reader, message_length);
+                notice_handler(&notice);
+                continue;
+            }
+
+            return (message_head[0], message_length);
         }
+    }
+
+    fn read_row_description (reader: &mut BufReader<TcpStream>, response_length: i32) -> Vec<DatabaseRowDescription> {
 
         //create vector big enough to hold the rest of the message
-        let mut row_description_body: Vec<u8> = vec![0; query_response_length as usize - 4];
+        let mut row_description_body: Vec<u8> = vec![0; response_length as usize - 4];
         //read from stream
         Self::read_from_db_stream(reader, &mut row_description_body);
 
@@ -1062,7 +4411,7 @@ impl DatabaseConnection {
 
             loop {
                 //check if the current array position is larger than the actual message
-                if array_pos > query_response_length as usize {
+                if array_pos > response_length as usize {
                     println!("no null terminator was found?");
                     break;
                 }
@@ -1131,13 +4480,13 @@ impl DatabaseConnection {
                         
                         row_descriptions.push(
                             DatabaseRowDescription {
-                                name: name,
-                                table_oid: table_oid,
-                                column_number: column_number,
-                                type_oid: type_oid,
-                                type_size: type_size,
-                                type_add_info: type_add_info,
-                                type_send_method: type_send_method
+                                name,
+                                table_oid,
+                                column_number,
+                                type_oid,
+                                type_size,
+                                type_add_info,
+                                type_send_method
 
                             }
                         );
@@ -1151,38 +4500,30 @@ impl DatabaseConnection {
                 }
             }
         }
-        
-        //read next message, its either command complete, a datarow or empty query
-        Self::read_rows(reader, row_descriptions, &mut rows);
-
-        //after successfull query, read the ready for new query command
-        Self::read_ready_command(reader);
-
-        return rows;
 
+        row_descriptions
     }
 
     fn read_rows (
         reader: &mut BufReader<TcpStream>, 
         row_descriptions: Vec<DatabaseRowDescription>, 
-        rows: &mut Vec<BTreeMap<String, Option<DatabaseValue>>>
-    ) {
+        rows: &mut Vec<DatabaseRow>,
+        notice_handler: fn(&DatabaseNotice)
+    ) -> Result<(), DatabaseError> {
         loop {
             //67 'C' is command complete
             //68 'D' is datarow
             //73 'I' is empty query
+            //69 'E' is error, for example when a value can not be calculated
 
-            //create vector to hold the head information of the response message
-            //1 byte identifyer, 4 bytes message length
-            let mut response_head: Vec<u8> = vec![0; 5];
-            Self::read_from_db_stream(reader, &mut response_head);
-            let response_length: i32 = i32::from_be_bytes(response_head[1..].try_into().unwrap());
+            //read the head information of the response message
+            let (response_type, response_length) = Self::read_message_head(reader, notice_handler);
 
             //check if the response matches expected messages
-            assert!(matches!(response_head[0], 67 | 68 | 73));
+            assert!(matches!(response_type, 67 | 68 | 69 | 73));
 
 
-            let packet_identifier = char::from_u32(response_head[0] as u32).unwrap();
+            let packet_identifier = char::from_u32(response_type as u32).unwrap();
             match  packet_identifier {
                 //datarow
                 'D' => {
@@ -1196,7 +4537,7 @@ impl DatabaseConnection {
                     let mut values = BTreeMap::new();
 
                     //loop through the columns
-                    for i in 0..number_of_columns as usize {
+                    for row_description in row_descriptions.iter().take(number_of_columns as usize) {
                         //get the length of the value (4 bytes)
                         let mut value_length: Vec<u8> = vec![0; 4];
                         Self::read_from_db_stream(reader, &mut value_length);
@@ -1217,7 +4558,7 @@ impl DatabaseConnection {
 
                                 //handle the value of the row depening on what kind of type the
                                 //field is
-                                match row_descriptions[i].type_oid {
+                                match row_description.type_oid {
                                     23 => {//23 = integer
 
                                         //turn the individual bytes into a string
@@ -1287,7 +4628,7 @@ impl DatabaseConnection {
                                     _ =>  {
                                         println!(
                                             "encountered database type oid which is not defined: {}", 
-                                            row_descriptions[i].type_oid
+                                            row_description.type_oid
                                         );
                                         None
                                     },
@@ -1296,7 +4637,7 @@ impl DatabaseConnection {
                         };
 
                         //insert the value with the desciption into the hash map
-                        values.insert(row_descriptions[i].name.clone(), value_option);
+                        values.insert(row_description.name.clone(), value_option);
 
                     }
 
@@ -1315,12 +4656,17 @@ impl DatabaseConnection {
                     println!("query empty");
                     break;
                 },
+                'E' => {
+                    return Err(Self::read_error(reader, response_length));
+                },
                 _ => {
                     break;   
                 },
 
             }
         }
+
+        Ok(())
     }
 
     fn read_complete_command (reader: &mut BufReader<TcpStream>, response_length: i32) -> String {
@@ -1333,42 +4679,559 @@ impl DatabaseConnection {
 
         //loop through the response from the db, end when a null terminator has been rechead
         //response from the db will always end with a null terminator
-        for vec_pos in 0..complete_tag.len() {
-            if complete_tag[vec_pos] == 0x00 {
+        for byte in complete_tag.iter() {
+            if *byte == 0x00 {
                 break;
             }
-            complete_tag_parse.push(complete_tag[vec_pos]);
+            complete_tag_parse.push(*byte);
         }
 
         //turn the u8 into a string
         let complete_tag_string = String::from(std::str::from_utf8(&complete_tag_parse[..]).unwrap());
 
         //return the string
-        return complete_tag_string;
+        complete_tag_string
 
     }
 
-    fn read_ready_command (reader: &mut BufReader<TcpStream>) {
+    fn read_ready_command (reader: &mut BufReader<TcpStream>, notice_handler: fn(&DatabaseNotice)) {
         //check query result and if db is ready for another query
-        //create vector to hold the head information of the response message
-        //1 byte identifyer, 4 bytes message length
-        let mut ready_command: Vec<u8> = vec![0; 6];
-        Self::read_from_db_stream(reader, &mut ready_command);
+        let (ready_type, _) = Self::read_message_head(reader, notice_handler);
+
+        //1 byte transaction status
+        let mut ready_status: Vec<u8> = vec![0; 1];
+        Self::read_from_db_stream(reader, &mut ready_status);
 
         //check if the response matches expected messages
         //90 = 'Z' ReadyForQuery
-        assert_eq!(ready_command[0], 90);
+        assert_eq!(ready_type, 90);
         //check if db connection is in status idle
         //73 = 'I'
-        assert_eq!(ready_command[5], 73);
+        assert_eq!(ready_status[0], 73);
     }
 
-    fn read_error (reader: &mut BufReader<TcpStream>, error_length: i32) {
-        println!("error");
+    //read the body of an error or notice response
+    //the body is a list of fields, each starting with a 1 byte field type
+    //followed by a null terminated string, the list ends with a null terminator
+    fn read_error (reader: &mut BufReader<TcpStream>, error_length: i32) -> DatabaseError {
         //read the error message
         let mut error_message: Vec<u8> = vec![0; error_length as usize - 4];
         Self::read_from_db_stream(reader, &mut error_message);
-        
 
+        let mut error = DatabaseError::default();
+
+        for field in error_message.split(|byte| *byte == 0x00) {
+            //the last field is empty because of the final null terminator
+            if field.is_empty() {
+                continue;
+            }
+
+            let value = String::from_utf8_lossy(&field[1..]).to_string();
+
+            match field[0] {
+                //'S' can be translated, 'V' is never localized and sent after 'S'
+                b'S' | b'V' => error.severity = DatabaseSeverity::parse(&value),
+                b'C' => error.code = value,
+                b'M' => error.message = value,
+                b'D' => error.detail = Some(value),
+                b'H' => error.hint = Some(value),
+                b'P' => error.position = value.parse().ok(),
+                _ => {},
+            }
+        }
+
+        error
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_json_values () {
+        let json = parse_json(r#" {"name": "meme", "count": 3, "big": 5000000000, "score": -1.5e2, "tags": [true, null]} "#).unwrap();
+
+        assert_eq!(json.get("name"), Some(&JsonType::String(String::from("meme"))));
+        assert_eq!(json.get("count"), Some(&JsonType::Number(3)));
+        assert_eq!(json.get("big"), Some(&JsonType::BigNumber(5000000000)));
+        assert_eq!(json.get("score"), Some(&JsonType::Float(-150.0)));
+        assert_eq!(json.get("tags"), Some(&JsonType::Array(vec![JsonType::Boolean(true), JsonType::Null])));
+    }
+
+    #[test]
+    fn parse_json_escapes () {
+        assert_eq!(parse_json(r#""a\"b\\c\/\n\t""#).unwrap(), JsonType::String(String::from("a\"b\\c/\n\t")));
+        assert_eq!(parse_json(r#""\u00e4""#).unwrap(), JsonType::String(String::from("\u{e4}")));
+        assert_eq!(parse_json(r#""ä""#).unwrap(), JsonType::String(String::from("ä")));
+    }
+
+    #[test]
+    fn parse_json_surrogate_pairs () {
+        assert_eq!(parse_json(r#""\ud83d\ude00""#).unwrap(), JsonType::String(String::from("\u{1f600}")));
+
+        let error = parse_json(r#""\ud83d""#).unwrap_err();
+        assert_eq!(error.message, "unpaired surrogate");
+        assert_eq!(error.offset, 3);
+
+        let error = parse_json(r#""\ude00""#).unwrap_err();
+        assert_eq!(error.message, "unpaired surrogate");
+
+        let error = parse_json(r#""\ud83d\u0041""#).unwrap_err();
+        assert_eq!(error.message, "invalid low surrogate");
+    }
+
+    #[test]
+    fn parse_json_depth_limit () {
+        let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+
+        assert!(parse_json(&nested(JSON_MAX_DEPTH)).is_ok());
+
+        let error = parse_json(&nested(JSON_MAX_DEPTH + 1)).unwrap_err();
+        assert_eq!(error.message, "json is nested too deep");
+        assert_eq!(error.offset, JSON_MAX_DEPTH);
+    }
+
+    #[test]
+    fn parse_json_error_offsets () {
+        assert_eq!(parse_json(r#"{"a": tru}"#).unwrap_err().offset, 6);
+        assert_eq!(parse_json("[1,]").unwrap_err().offset, 3);
+        assert_eq!(parse_json("{} x").unwrap_err().offset, 3);
+        assert_eq!(parse_json(r#"{"a" 1}"#).unwrap_err().offset, 5);
+        assert_eq!(parse_json("01").unwrap_err().offset, 1);
+        assert_eq!(parse_json("\"abc").unwrap_err().offset, 4);
+        assert_eq!(parse_json("").unwrap_err().offset, 0);
+    }
+
+    #[test]
+    fn parse_range_header_ranges () {
+        assert_eq!(parse_range_header("bytes=0-99", 1000), ByteRanges::Satisfiable(vec![(0, 99)]));
+        assert_eq!(parse_range_header("bytes=200-", 1000), ByteRanges::Satisfiable(vec![(200, 999)]));
+        assert_eq!(parse_range_header("bytes=0-0, 10-19", 1000), ByteRanges::Satisfiable(vec![(0, 0), (10, 19)]));
+
+        //the end is limited to the last byte of the file
+        assert_eq!(parse_range_header("bytes=900-5000", 1000), ByteRanges::Satisfiable(vec![(900, 999)]));
+    }
+
+    #[test]
+    fn parse_range_header_suffix () {
+        assert_eq!(parse_range_header("bytes=-50", 1000), ByteRanges::Satisfiable(vec![(950, 999)]));
+
+        //a suffix longer than the file is the whole file
+        assert_eq!(parse_range_header("bytes=-5000", 1000), ByteRanges::Satisfiable(vec![(0, 999)]));
+
+        assert_eq!(parse_range_header("bytes=-0", 1000), ByteRanges::Unsatisfiable);
+    }
+
+    #[test]
+    fn parse_range_header_overflow () {
+        assert_eq!(parse_range_header("bytes=0-99999999999999999999", 1000), ByteRanges::Ignore);
+        assert_eq!(parse_range_header("bytes=99999999999999999999-", 1000), ByteRanges::Ignore);
+        assert_eq!(parse_range_header("bytes=-99999999999999999999", 1000), ByteRanges::Ignore);
+        assert_eq!(parse_range_header("bytes=0-18446744073709551615", 1000), ByteRanges::Satisfiable(vec![(0, 999)]));
+        assert_eq!(parse_range_header("bytes=-18446744073709551615", 1000), ByteRanges::Satisfiable(vec![(0, 999)]));
+    }
+
+    #[test]
+    fn parse_range_header_unsatisfiable () {
+        assert_eq!(parse_range_header("bytes=1000-", 1000), ByteRanges::Unsatisfiable);
+        assert_eq!(parse_range_header("bytes=1000-2000, 5000-", 1000), ByteRanges::Unsatisfiable);
+        assert_eq!(parse_range_header("bytes=0-", 0), ByteRanges::Unsatisfiable);
+        assert_eq!(parse_range_header("bytes=-10", 0), ByteRanges::Unsatisfiable);
+    }
+
+    #[test]
+    fn parse_range_header_invalid () {
+        assert_eq!(parse_range_header("items=0-99", 1000), ByteRanges::Ignore);
+        assert_eq!(parse_range_header("bytes=", 1000), ByteRanges::Ignore);
+        assert_eq!(parse_range_header("bytes=99-0", 1000), ByteRanges::Ignore);
+        assert_eq!(parse_range_header("bytes=a-b", 1000), ByteRanges::Ignore);
+        assert_eq!(parse_range_header("bytes=5", 1000), ByteRanges::Ignore);
+
+        let too_many = format!("bytes={}", vec!["0-0"; MAX_RANGES + 1].join(","));
+        assert_eq!(parse_range_header(&too_many, 1000), ByteRanges::Ignore);
+    }
+
+    #[test]
+    fn parse_http_date_round_trip () {
+        let date = "Sun, 06 Nov 1994 08:49:37 GMT";
+        let time = parse_http_date(date).unwrap();
+
+        assert_eq!(time, UNIX_EPOCH + Duration::from_secs(784111777));
+        assert_eq!(http_date(time), date);
+
+        //leap day and the first day of a year
+        for seconds in [0, 951782400, 1709208000, 1735689600, 4102444800] {
+            let time = UNIX_EPOCH + Duration::from_secs(seconds);
+            assert_eq!(parse_http_date(&http_date(time)), Some(time));
+        }
+    }
+
+    #[test]
+    fn parse_http_date_invalid () {
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date("Sun Nov  6 08:49:37 1994"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 UTC"), None);
+        assert_eq!(parse_http_date("Sun, 06 Foo 1994 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date("Sun, 32 Nov 1994 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 24:00:00 GMT"), None);
+        assert_eq!(parse_http_date("Wed, 31 Dec 1969 23:59:59 GMT"), None);
+        assert_eq!(parse_http_date(""), None);
+    }
+
+    #[test]
+    fn percent_decode_sequences () {
+        assert_eq!(percent_decode("a%20b%2Fc"), b"a b/c");
+        assert_eq!(percent_decode("%e4%C3%a4"), vec![0xe4, 0xc3, 0xa4]);
+        assert_eq!(percent_decode_string("%C3%A4"), "\u{e4}");
+        assert_eq!(percent_decode_string("%FF"), "\u{fffd}");
+    }
+
+    #[test]
+    fn percent_decode_invalid_sequences () {
+        assert_eq!(percent_decode("100%"), b"100%");
+        assert_eq!(percent_decode("%2"), b"%2");
+        assert_eq!(percent_decode("%zz%41"), b"%zzA");
+        assert_eq!(percent_decode("%+1"), b"%+1");
+        assert_eq!(percent_decode("%%41"), b"%A");
+    }
+
+    #[test]
+    fn parse_cookies_pairs () {
+        let cookies = parse_cookies("session=rws_abc; csrf=\"rwc_def\";theme = dark ");
+
+        assert_eq!(cookies.get("session").map(String::as_str), Some("rws_abc"));
+        assert_eq!(cookies.get("csrf").map(String::as_str), Some("rwc_def"));
+        assert_eq!(cookies.get("theme").map(String::as_str), Some("dark"));
+        assert_eq!(cookies.len(), 3);
+    }
+
+    #[test]
+    fn parse_cookies_first_value_wins () {
+        let cookies = parse_cookies("session=first; session=second");
+        assert_eq!(cookies.get("session").map(String::as_str), Some("first"));
+    }
+
+    #[test]
+    fn parse_cookies_invalid_pairs () {
+        let cookies = parse_cookies("flag; =value; ;empty=; token=a=b");
+
+        assert_eq!(cookies.get("empty").map(String::as_str), Some(""));
+        assert_eq!(cookies.get("token").map(String::as_str), Some("a=b"));
+        assert_eq!(cookies.len(), 2);
+    }
+
+    #[test]
+    fn token_format_valid_generated_tokens () {
+        let token = generate_token();
+        assert!(token.starts_with(TOKEN_PREFIX));
+        assert!(token_format_valid(&token, TOKEN_PREFIX));
+
+        let csrf_token = generate_prefixed_token(CSRF_TOKEN_PREFIX, TOKEN_BYTES);
+        assert!(token_format_valid(&csrf_token, CSRF_TOKEN_PREFIX));
+        assert!(!token_format_valid(&csrf_token, TOKEN_PREFIX));
+    }
+
+    #[test]
+    fn token_format_valid_rejects_changed_tokens () {
+        let token = generate_token();
+
+        //one changed character in the random part or the checksum
+        for position in [TOKEN_PREFIX.len(), token.len() - 1] {
+            let mut changed = token.clone().into_bytes();
+            changed[position] = if changed[position] == b'A' { b'B' } else { b'A' };
+            assert!(!token_format_valid(&String::from_utf8(changed).unwrap(), TOKEN_PREFIX));
+        }
+
+        assert!(!token_format_valid(&token[TOKEN_PREFIX.len()..], TOKEN_PREFIX));
+        assert!(!token_format_valid(&token[..token.len() - 1], TOKEN_PREFIX));
+        assert!(!token_format_valid(TOKEN_PREFIX, TOKEN_PREFIX));
+        assert!(!token_format_valid("rws_\u{e4}\u{e4}\u{e4}\u{e4}", TOKEN_PREFIX));
+        assert!(!token_format_valid("", TOKEN_PREFIX));
+    }
+
+    //reads a chunked body, which a client sends over a local connection
+    fn read_chunked (data: Vec<u8>, max_size: usize) -> (std::io::Result<String>, HeaderMap) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            //the server may stop reading early, the rest of the data is not needed
+            let _ = stream.write_all(&data);
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        let mut buf_reader = BufReader::new(&stream);
+        let mut trailers = HeaderMap::new();
+        let body = read_http_body_chunked(&mut buf_reader, &mut trailers, max_size);
+
+        client.join().unwrap();
+        (body, trailers)
+    }
+
+    #[test]
+    fn read_http_body_chunked_body_and_trailers () {
+        let (body, trailers) = read_chunked(b"5;ext=1\r\nhello\r\n6\r\n world\r\n0\r\nChecksum: abc\r\n\r\n".to_vec(), 1024);
+
+        assert_eq!(body.unwrap(), "hello world");
+        assert_eq!(trailers.get("Checksum"), Some("abc"));
+    }
+
+    #[test]
+    fn read_http_body_chunked_limits () {
+        assert!(read_chunked(b"ffffffffffffffff\r\n".to_vec(), 1024).0.is_err());
+        assert!(read_chunked(b"a\r\n0123456789\r\n0\r\n\r\n".to_vec(), 5).0.is_err());
+
+        assert!(read_chunked(vec![b'0'; CHUNK_SIZE_LINE_MAX + 1], 1024).0.is_err());
+
+        let mut long_trailer = b"0\r\nX: ".to_vec();
+        long_trailer.extend(vec![b'a'; TRAILER_MAX_SIZE]);
+        assert!(read_chunked(long_trailer, 1024).0.is_err());
+    }
+
+    #[test]
+    fn parse_request_line_incomplete () {
+        assert!(parse_request_line(String::from("GET")).empty);
+        assert!(parse_request_line(String::from("GET /")).empty);
+        assert!(parse_request_line(String::from("GET / HTTP/1.1 extra")).empty);
+
+        let request_line = parse_request_line(String::from("GET /a?b=c HTTP/1.1"));
+        assert!(!request_line.empty);
+        assert_eq!(request_line.path, "/a");
+        assert_eq!(request_line.protocol, "HTTP/1.1");
+    }
+
+    #[test]
+    fn thread_pool_survives_panicking_job () {
+        let pool = ThreadPool::new(1);
+        let load = pool.load();
+
+        pool.execute(|| panic!("job panicked"));
+
+        //the same worker has to take the next job
+        let (sender, receiver) = mpsc::channel();
+        pool.execute(move || sender.send(()).unwrap());
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        //the count is decreased right after the job has returned
+        let start = Instant::now();
+        while load.saturated() && start.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(!load.saturated());
+    }
+
+    fn empty_handler (_request: &HTTPRequest, _database_connections: Arc<DatabaseConnectionPool>) -> HTTPResponse {
+        status_response(StatusCode::Ok)
+    }
+
+    #[test]
+    fn router_captures_params () {
+        let mut router = Router::new();
+        router.get("/api/users/:id", empty_handler);
+        router.get("/files/*path", empty_handler);
+
+        match router.find_route(Method::GET, "/api/users/a%20b") {
+            RouteMatch::Found(_, params) => assert_eq!(params.get("id").map(String::as_str), Some("a b")),
+            _ => panic!("route not found"),
+        }
+
+        match router.find_route(Method::GET, "/files/css/site.css") {
+            RouteMatch::Found(_, params) => assert_eq!(params.get("path").map(String::as_str), Some("css/site.css")),
+            _ => panic!("route not found"),
+        }
+
+        assert!(matches!(router.find_route(Method::GET, "/api/users/"), RouteMatch::NotFound));
+        assert!(matches!(router.find_route(Method::GET, "/api/users/1/posts"), RouteMatch::NotFound));
+    }
+
+    #[test]
+    fn router_not_found () {
+        let router = create_router();
+
+        assert!(matches!(router.find_route(Method::POST, "/api/does/not/exist"), RouteMatch::NotFound));
+        assert!(matches!(router.find_route(Method::POST, "/index.html"), RouteMatch::NotFound));
+
+        //paths without a route are static files
+        assert!(matches!(router.find_route(Method::GET, "/index.html"), RouteMatch::Found(_, _)));
+    }
+
+    #[test]
+    fn router_method_not_allowed () {
+        let router = create_router();
+
+        for method in [Method::GET, Method::PUT] {
+            match router.find_route(method, "/api/login/logon") {
+                RouteMatch::MethodNotAllowed(allowed_methods) => assert_eq!(allowed_methods, vec![Method::POST]),
+                _ => panic!("expected method not allowed"),
+            }
+        }
+    }
+
+    //directory with a file, a hidden file and a file outside, which is removed after the test
+    struct StaticRoot {
+        base: PathBuf,
+    }
+
+    impl StaticRoot {
+        fn new (name: &str) -> StaticRoot {
+            let base = std::env::temp_dir().join(format!("webserver-{}-{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&base);
+
+            std::fs::create_dir_all(base.join("root/dir")).unwrap();
+            std::fs::write(base.join("root/a.txt"), "a").unwrap();
+            std::fs::write(base.join("root/login.html"), "login").unwrap();
+            std::fs::write(base.join("root/.secret"), "secret").unwrap();
+            std::fs::write(base.join("outside.txt"), "outside").unwrap();
+
+            StaticRoot { base }
+        }
+
+        fn root (&self) -> String {
+            self.base.join("root").to_string_lossy().to_string()
+        }
+    }
+
+    impl Drop for StaticRoot {
+        fn drop (&mut self) {
+            let _ = std::fs::remove_dir_all(&self.base);
+        }
+    }
+
+    fn static_options (symlinks: SymlinkPolicy) -> StaticFileOptions {
+        StaticFileOptions { symlinks, allow_dotfiles: false }
+    }
+
+    #[test]
+    fn resolve_static_path_files () {
+        let static_root = StaticRoot::new("files");
+        let options = static_options(SymlinkPolicy::FollowWithinRoot);
+        let root = static_root.root();
+
+        let file = resolve_static_path(&root, "/a.txt", &options).unwrap();
+        assert_eq!(std::fs::read_to_string(file).unwrap(), "a");
+
+        let file = resolve_static_path(&root, "/", &options).unwrap();
+        assert_eq!(std::fs::read_to_string(file).unwrap(), "login");
+
+        let file = resolve_static_path(&root, "/dir/../%61.txt", &options).unwrap();
+        assert_eq!(std::fs::read_to_string(file).unwrap(), "a");
+
+        assert_eq!(resolve_static_path(&root, "/missing.txt", &options), Err(StatusCode::NotFound));
+        assert_eq!(resolve_static_path(&root, "/dir", &options), Err(StatusCode::NotFound));
+        assert_eq!(resolve_static_path(&root, "/a.txt/", &options), Err(StatusCode::NotFound));
+    }
+
+    #[test]
+    fn resolve_static_path_rejects_traversal () {
+        let static_root = StaticRoot::new("traversal");
+        let options = static_options(SymlinkPolicy::FollowWithinRoot);
+        let root = static_root.root();
+
+        assert_eq!(resolve_static_path(&root, "/../outside.txt", &options), Err(StatusCode::BadRequest));
+        assert_eq!(resolve_static_path(&root, "/%2e%2e/outside.txt", &options), Err(StatusCode::BadRequest));
+        assert_eq!(resolve_static_path(&root, "/dir/..%2f..%2foutside.txt", &options), Err(StatusCode::BadRequest));
+        assert_eq!(resolve_static_path(&root, "/a.txt%00.html", &options), Err(StatusCode::BadRequest));
+        assert_eq!(resolve_static_path(&root, "/%ff", &options), Err(StatusCode::BadRequest));
+    }
+
+    #[test]
+    fn resolve_static_path_hides_dotfiles () {
+        let static_root = StaticRoot::new("dotfiles");
+        let options = static_options(SymlinkPolicy::FollowWithinRoot);
+        let root = static_root.root();
+
+        assert_eq!(resolve_static_path(&root, "/.secret", &options), Err(StatusCode::NotFound));
+        assert_eq!(resolve_static_path(&root, "/%2esecret", &options), Err(StatusCode::NotFound));
+
+        let options = StaticFileOptions { symlinks: SymlinkPolicy::FollowWithinRoot, allow_dotfiles: true };
+        assert!(resolve_static_path(&root, "/.secret", &options).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolve_static_path_symlinks () {
+        let static_root = StaticRoot::new("symlinks");
+        let root = static_root.root();
+
+        std::os::unix::fs::symlink(static_root.base.join("outside.txt"), static_root.base.join("root/outside.txt")).unwrap();
+        std::os::unix::fs::symlink(static_root.base.join("root/a.txt"), static_root.base.join("root/inside.txt")).unwrap();
+
+        let deny = static_options(SymlinkPolicy::Deny);
+        assert_eq!(resolve_static_path(&root, "/outside.txt", &deny), Err(StatusCode::Forbidden));
+        assert_eq!(resolve_static_path(&root, "/inside.txt", &deny), Err(StatusCode::Forbidden));
+
+        let within_root = static_options(SymlinkPolicy::FollowWithinRoot);
+        assert_eq!(resolve_static_path(&root, "/outside.txt", &within_root), Err(StatusCode::Forbidden));
+        assert!(resolve_static_path(&root, "/inside.txt", &within_root).is_ok());
+
+        let follow = static_options(SymlinkPolicy::Follow);
+        let file = resolve_static_path(&root, "/outside.txt", &follow).unwrap();
+        assert_eq!(std::fs::read_to_string(file).unwrap(), "outside");
+    }
+
+    #[test]
+    fn json_encode_escapes_strings () {
+        let value = JsonType::String(String::from("say \"hi\" \\ path \n\r\t\x08\x0c\x01\x1f ä"));
+
+        assert_eq!(
+            json_encode(&value),
+            r#""say \"hi\" \\ path \n\r\t\b\f\u0001\u001f ä""#
+        );
+    }
+
+    #[test]
+    fn json_encode_escaped_keys () {
+        let mut object = BTreeMap::new();
+        object.insert(String::from("a\"b"), JsonType::Null);
+
+        assert_eq!(json_encode(&JsonType::Object(object)), r#"{"a\"b": null}"#);
+    }
+
+    #[test]
+    fn json_encode_non_finite_floats () {
+        let values = vec![
+            JsonType::Float(f64::NAN),
+            JsonType::Float(f64::INFINITY),
+            JsonType::Float(f64::NEG_INFINITY),
+            JsonType::Float(1.5),
+        ];
+
+        assert_eq!(json_encode(&values), "[null, null, null, 1.5]");
+    }
+
+    #[test]
+    fn json_encode_stable_shape () {
+        //a vector with one value is still an array
+        assert_eq!(json_encode(&vec![JsonType::Number(1)]), "[1]");
+        assert_eq!(json_encode(&Vec::<JsonType>::new()), "[]");
+
+        let mut row: BTreeMap<String, Option<JsonType>> = BTreeMap::new();
+        row.insert(String::from("name"), Some(JsonType::String(String::from("meme"))));
+        row.insert(String::from("missing"), None);
+
+        assert_eq!(json_encode(&vec![row]), r#"[{"missing": null, "name": "meme"}]"#);
+    }
+
+    #[test]
+    fn load_cache_control_rules_from_file () {
+        let path = std::env::temp_dir().join(format!("webserver-cache-control-{}.conf", std::process::id()));
+        std::fs::write(&path, "# static files\n/assets/  public, max-age=31536000\n\n/api/ no-store # answers\n/broken\n").unwrap();
+
+        let rules = load_cache_control_rules(&path.to_string_lossy());
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(rules, vec![
+            (String::from("/assets/"), String::from("public, max-age=31536000")),
+            (String::from("/api/"), String::from("no-store")),
+        ]);
+    }
+
+    #[test]
+    fn load_cache_control_rules_default () {
+        let rules = load_cache_control_rules("/nonexistent/cache-control.conf");
+        assert_eq!(rules.len(), DEFAULT_CACHE_CONTROL_RULES.len());
+        assert_eq!(rules[0].0, "/assets/");
     }
 }
@@ -1,12 +1,24 @@
 use std::{
+    collections::HashMap,
     io::BufReader,
     net::{TcpListener, TcpStream},
     sync::Arc,
+    time::Duration,
 };
 use webserver::*;
 
 mod constants;
 
+//seconds a connection stays open without a new request
+//the connection holds a worker while it waits, so the time is kept short
+const KEEP_ALIVE_TIMEOUT: u64 = 2;
+//maximum number of requests answered over one connection
+const KEEP_ALIVE_MAX_REQUESTS: usize = 100;
+//largest request body the server reads, bigger requests are rejected
+const MAX_BODY_SIZE: usize = 10 * 1024 * 1024;
+//number of connections, which are handled at the same time
+const WORKER_THREADS: usize = 32;
+
 fn main() {
 
     println!("root path: {}", constants::ROOT);
@@ -19,87 +31,172 @@ fn main() {
         "memeoff" //database
     ));
 
+    if let Err(err) = create_tables(&database_connections) {
+        println!("could not create tables: {}", err);
+        return;
+    }
+
+    //admin command, hash all passwords which are still stored in plaintext and exit
+    //usage: webserver rehash-passwords
+    if std::env::args().nth(1).as_deref() == Some("rehash-passwords") {
+        match rehash_plaintext_passwords(&database_connections) {
+            Ok(updated) => println!("rehashed passwords of {} users", updated),
+            Err(err) => println!("could not rehash passwords: {}", err),
+        }
+        return;
+    }
+
+    //routes are shared by all threads
+    let router = Arc::new(create_router());
+
     let listener = TcpListener::bind("212.132.120.118:7878").unwrap();
-    let threadpool = ThreadPool::new(8);
+    let threadpool = ThreadPool::new(WORKER_THREADS);
 
     println!("after pool creation");
 
     for stream in listener.incoming() {
         let stream = stream.unwrap();
         let database_connections_clone = Arc::clone(&database_connections);
+        let router_clone = Arc::clone(&router);
+        let pool_load = threadpool.load();
         
         threadpool.execute(|| {
-            handle_connection(stream, database_connections_clone);
+            handle_connection(stream, database_connections_clone, router_clone, pool_load);
         });
     }
 
 }
 
-fn handle_connection(stream: TcpStream, database_connections: Arc<DatabaseConnectionPool>) {
+fn handle_connection(
+    stream: TcpStream, 
+    database_connections: Arc<DatabaseConnectionPool>, 
+    router: Arc<Router>,
+    pool_load: PoolLoad
+) {
 
     println!("!!!!!!!!!!!!!!!!!!!!!!!!!!new connection");
 
+    //close idle connections, reading a new request fails after the timeout
+    if let Err(err) = stream.set_read_timeout(Some(Duration::from_secs(KEEP_ALIVE_TIMEOUT))) {
+        println!("could not set read timeout: {}", err);
+        return;
+    }
+
     //create empty read to read stream into
+    //the reader is kept for the whole connection, so pipelined requests
+    //which are already buffered are not lost
     let mut buf_reader = BufReader::new(&stream);
 
-    let request_line = read_request_line(&mut buf_reader);
+    //answer the requests on the connection one after another
+    for request_number in 1..=KEEP_ALIVE_MAX_REQUESTS {
 
-    //debug
-    println!("request line: {}", request_line);
+        let request_line = read_request_line(&mut buf_reader);
 
-    //parse the request line
-    let request_line = parse_request_line(request_line);
+        //parse the request line
+        let request_line = parse_request_line(request_line);
 
-    //check if request line is empty
-    if request_line.empty {
-        println!("handle function says request line is empty");
-        return;
-    }
-
-    //read the headers
-    let http_headers = read_http_headers(&mut buf_reader);
+        //check if request line is empty
+        //happens when the client closed the connection or the timeout is reached
+        if request_line.empty {
+            println!("handle function says request line is empty");
+            return;
+        }
 
-    //if the header contains information for the accept of media type
-    //parse the information
-    let _header_accept = match http_headers.contains_key("Accept") {
-        true => Some(parse_header_accept(&http_headers["Accept"])),
-        false => None,
+        //read the headers
+        let http_headers = read_http_headers(&mut buf_reader);
 
-    };
+        //if the header contains information for the accept of media type
+        //parse the information
+        let _header_accept = http_headers.get("Accept").map(parse_header_accept);
 
-    let _content_type = match http_headers.get("Content-Type") {
-        Some(ctype) => ctype.to_string(),
-        None => String::from("no content type defined"),
-    };
-
-    //TODO rewrite this, so that a body variable is only created, if there is content
-    //variable for body data
-    let mut body = String::from("");
+        let _content_type = match http_headers.get("Content-Type") {
+            Some(ctype) => ctype.to_string(),
+            None => String::from("no content type defined"),
+        };
 
-    //if its a post request, check if there is a body
-    if let Method::POST = request_line.method {
+        //check if the connection stays open after this request
+        //when every worker is busy, the connection is closed, so waiting connections get a worker
+        let keep_alive = request_number < KEEP_ALIVE_MAX_REQUESTS
+            && !pool_load.saturated()
+            && connection_keep_alive(&request_line.protocol, &http_headers);
+
+        //read the content of the body
+        //the body is read for every method, so the next request starts at the right position
+        //if the body is sent in chunks, the length is not known in advance
+        let mut trailers = HeaderMap::new();
+        let body = if transfer_encoding_chunked(&http_headers) {
+            read_http_body_chunked(&mut buf_reader, &mut trailers, MAX_BODY_SIZE)
+        } else if http_headers.contains_key("Transfer-Encoding") {
+            //the end of the request can not be determined for other transfer encodings
+            println!("unsupported transfer encoding: {}", http_headers.get("Transfer-Encoding").unwrap_or(""));
+            return;
+        } else {
+            match http_headers.get("Content-Length") {
+                Some(length) => match length.parse() {
+                    //the body is not read, so the connection is closed after the response
+                    Ok(clength) if clength > MAX_BODY_SIZE => {
+                        println!("body too large: {}", clength);
+                        if let Ok(request_stream) = stream.try_clone() {
+                            let request = HTTPRequest {
+                                stream: request_stream,
+                                request_line,
+                                headers: http_headers,
+                                body: String::new(),
+                                trailers: HeaderMap::new(),
+                                keep_alive: false,
+                                path_params: HashMap::new(),
+                                principal: None,
+                            };
+                            send_response(request, status_response(StatusCode::PayloadTooLarge));
+                        }
+                        return;
+                    },
+                    Ok(clength) => read_http_body(&mut buf_reader, clength),
+                    Err(_) => {
+                        //the end of the request is unknown, so the connection can not be used further
+                        println!("invalid content length: {}", length);
+                        return;
+                    },
+                },
+                None => Ok(String::from("")),
+            }
+        };
 
-        //create option for content lengh
-        let content_length: Option<usize> = match http_headers.get("Content-Length") {
-            Some(length) => Some(length.parse().unwrap()), //TODO handling, if parse fails
-            None => None,
+        //if the body could not be read, the connection can not be used further
+        let body = match body {
+            Ok(body) => body,
+            Err(err) => {
+                println!("could not read body: {}", err);
+                return;
+            },
         };
 
-        //read the content of the body of the post request
-        body = match content_length {
-            Some(clength) => {
-                read_http_body(&mut buf_reader, clength)
+        //the request gets its own handle to the connection
+        //the connection is closed, when the original stream is dropped
+        let request_stream = match stream.try_clone() {
+            Ok(request_stream) => request_stream,
+            Err(err) => {
+                println!("could not clone stream: {}", err);
+                return;
             },
-            None => String::from("")
         };
-    }
 
-    let full_request = HTTPRequest {
-        stream: stream,
-        request_line: request_line ,
-        body: body,
-    };
+        let full_request = HTTPRequest {
+            stream: request_stream,
+            request_line,
+            headers: http_headers,
+            body,
+            trailers,
+            keep_alive,
+            path_params: HashMap::new(),
+            principal: None,
+        };
 
-    send_http_response(full_request, database_connections);
+        router.handle(full_request, Arc::clone(&database_connections));
+
+        if !keep_alive {
+            break;
+        }
+    }
 
 }
@@ -0,0 +1,336 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::DatabaseConnectionPool;
+use crate::HTTPRequest;
+use crate::HTTPResponse;
+use crate::Method;
+use crate::Middleware;
+use crate::Next;
+use crate::StatusCode;
+use crate::TokenCheck;
+use crate::check_token;
+use crate::load_principal;
+use crate::api_database_error;
+use crate::api_error;
+use crate::request_token;
+use crate::constant_time_eq;
+use crate::CSRF_COOKIE;
+use crate::CSRF_HEADER;
+use crate::SESSION_COOKIE;
+use crate::status_response;
+
+//writes one line per request with client, method, path, status and duration
+pub struct AccessLog;
+
+impl Middleware for AccessLog {
+    fn handle (
+        &self,
+        request: &mut HTTPRequest,
+        database_connections: &Arc<DatabaseConnectionPool>,
+        next: Next
+    ) -> HTTPResponse {
+        let start = Instant::now();
+
+        //read before the handler runs, the handler may change the request
+        let peer = match request.stream.peer_addr() {
+            Ok(address) => address.to_string(),
+            Err(_) => String::from("-"),
+        };
+        let method = request.request_line.method;
+        let path = request.request_line.path.clone();
+
+        let response = next.run(request, database_connections);
+
+        println!(
+            "{} {} {} {} {}ms",
+            peer,
+            method.as_str(),
+            path,
+            response.status.code(),
+            start.elapsed().as_millis()
+        );
+
+        response
+    }
+}
+
+//rejects requests with a body larger than the limit
+pub struct BodyLimit {
+    max_size: usize,
+}
+
+impl BodyLimit {
+    pub fn new (max_size: usize) -> BodyLimit {
+        BodyLimit { max_size }
+    }
+}
+
+impl Middleware for BodyLimit {
+    fn handle (
+        &self,
+        request: &mut HTTPRequest,
+        database_connections: &Arc<DatabaseConnectionPool>,
+        next: Next
+    ) -> HTTPResponse {
+        if request.body.len() > self.max_size {
+            return status_response(StatusCode::PayloadTooLarge);
+        }
+
+        next.run(request, database_connections)
+    }
+}
+
+//only lets requests through, which carry a token of a user
+//the token is taken from the json field 'UserToken', the header 'Authorization: Bearer <token>'
+//or the session cookie
+//the user is attached to the request as principal
+pub struct TokenAuth;
+
+impl TokenAuth {
+    //set the principal of the request, returns the response for requests without valid token
+    fn authenticate (
+        request: &mut HTTPRequest, 
+        database_connections: &Arc<DatabaseConnectionPool>
+    ) -> Result<(), HTTPResponse> {
+        //already authenticated by an earlier middleware
+        if request.principal.is_some() {
+            return Ok(());
+        }
+
+        let token = request_token(request);
+
+        let user = match check_token(database_connections, &token) {
+            Ok(TokenCheck::Valid(user)) => user,
+            Ok(token_check) => return Err(unauthorized(token_check.message())),
+            Err(error) => return Err(api_database_error(error)),
+        };
+
+        match load_principal(database_connections, user) {
+            Ok(principal) => {
+                request.principal = Some(principal);
+                Ok(())
+            },
+            Err(error) => Err(api_database_error(error)),
+        }
+    }
+}
+
+impl Middleware for TokenAuth {
+    fn handle (
+        &self,
+        request: &mut HTTPRequest,
+        database_connections: &Arc<DatabaseConnectionPool>,
+        next: Next
+    ) -> HTTPResponse {
+        match TokenAuth::authenticate(request, database_connections) {
+            Ok(()) => next.run(request, database_connections),
+            Err(response) => response,
+        }
+    }
+}
+
+//only lets requests through, whose user has the role
+//answers 401 without valid token and 403 if the role is missing
+pub struct RequireRole {
+    role: String,
+}
+
+impl RequireRole {
+    pub fn new (role: &str) -> RequireRole {
+        RequireRole { role: role.to_string() }
+    }
+}
+
+impl Middleware for RequireRole {
+    fn handle (
+        &self,
+        request: &mut HTTPRequest,
+        database_connections: &Arc<DatabaseConnectionPool>,
+        next: Next
+    ) -> HTTPResponse {
+        if let Err(response) = TokenAuth::authenticate(request, database_connections) {
+            return response;
+        }
+
+        let has_role = match &request.principal {
+            Some(principal) => principal.has_role(&self.role),
+            None => false,
+        };
+
+        if !has_role {
+            let mut response = api_error("User is not allowed to use this function");
+            response.status = StatusCode::Forbidden;
+            return response;
+        }
+
+        next.run(request, database_connections)
+    }
+}
+
+//response for requests without valid token
+pub fn unauthorized (message: &str) -> HTTPResponse {
+    let mut response = api_error(message)
+        .header("WWW-Authenticate", "Bearer");
+    response.status = StatusCode::Unauthorized;
+    response
+}
+
+//adds the headers for cross origin requests and answers preflight requests
+pub struct Cors {
+    allowed_origins: Vec<String>,
+    allowed_methods: Vec<Method>,
+    allowed_headers: Vec<String>,
+    max_age: u32,
+}
+
+impl Cors {
+    //allow the given origins, '*' allows every origin
+    pub fn new (allowed_origins: &[&str]) -> Cors {
+        Cors {
+            allowed_origins: allowed_origins.iter().map(|origin| origin.to_string()).collect(),
+            allowed_methods: vec![Method::GET, Method::POST],
+            allowed_headers: vec![String::from("Content-Type"), String::from("Authorization")],
+            max_age: 600,
+        }
+    }
+
+    pub fn methods (mut self, methods: &[Method]) -> Cors {
+        self.allowed_methods = methods.to_vec();
+        self
+    }
+
+    pub fn headers (mut self, headers: &[&str]) -> Cors {
+        self.allowed_headers = headers.iter().map(|header| header.to_string()).collect();
+        self
+    }
+
+    //seconds the browser may cache the answer to a preflight request
+    pub fn max_age (mut self, max_age: u32) -> Cors {
+        self.max_age = max_age;
+        self
+    }
+
+    fn origin_allowed (&self, origin: &str) -> bool {
+        self.allowed_origins.iter().any(|allowed| allowed == "*" || allowed == origin)
+    }
+}
+
+impl Middleware for Cors {
+    fn handle (
+        &self,
+        request: &mut HTTPRequest,
+        database_connections: &Arc<DatabaseConnectionPool>,
+        next: Next
+    ) -> HTTPResponse {
+        //requests without origin are not cross origin requests
+        let origin = match request.headers.get("Origin") {
+            Some(origin) => origin.to_string(),
+            None => return next.run(request, database_connections),
+        };
+
+        let origin_allowed = self.origin_allowed(&origin);
+
+        //preflight request, answered without calling the handler
+        if request.request_line.method == Method::OPTIONS
+            && request.headers.contains_key("Access-Control-Request-Method") {
+
+            let mut response = HTTPResponse::new(StatusCode::NoContent)
+                .header("Vary", "Origin");
+
+            if origin_allowed {
+                let methods = self.allowed_methods
+                    .iter()
+                    .map(|method| method.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(", ");
+
+                response = response
+                    .header("Access-Control-Allow-Origin", &origin)
+                    .header("Access-Control-Allow-Methods", &methods)
+                    .header("Access-Control-Allow-Headers", &self.allowed_headers.join(", "))
+                    .header("Access-Control-Max-Age", &self.max_age.to_string());
+            }
+
+            return response;
+        }
+
+        let mut response = next.run(request, database_connections);
+
+        response.headers.append("Vary", "Origin");
+        if origin_allowed {
+            response.headers.insert("Access-Control-Allow-Origin", &origin);
+        }
+
+        response
+    }
+}
+
+//protects routes, which accept the session cookie, against requests from other sites
+//requests, which change data, have to come from an allowed origin and carry the csrf token
+//from the csrf cookie in the header X-CSRF-Token as well
+//requests without the session cookie are not checked, other sites can not send the token for them
+pub struct Csrf {
+    allowed_origins: Vec<String>,
+}
+
+impl Csrf {
+    pub fn new (allowed_origins: &[&str]) -> Csrf {
+        Csrf {
+            allowed_origins: allowed_origins.iter().map(|origin| origin.to_string()).collect(),
+        }
+    }
+
+    //origin of the request from the header Origin, or from the header Referer for older browsers
+    fn request_origin (request: &HTTPRequest) -> Option<String> {
+        if let Some(origin) = request.headers.get("Origin") {
+            return Some(origin.to_string());
+        }
+
+        //the origin is the referer up to the path, 'https://host:port'
+        let referer = request.headers.get("Referer")?;
+        let (scheme, rest) = referer.split_once("://")?;
+        let host = rest.split(['/', '?', '#']).next()?;
+
+        Some(format!("{}://{}", scheme, host))
+    }
+
+    fn check (&self, request: &HTTPRequest) -> bool {
+        let origin_allowed = match Csrf::request_origin(request) {
+            Some(origin) => self.allowed_origins.iter().any(|allowed| allowed.eq_ignore_ascii_case(&origin)),
+            None => false,
+        };
+
+        if !origin_allowed {
+            return false;
+        }
+
+        match (request.cookie(CSRF_COOKIE), request.headers.get(CSRF_HEADER)) {
+            (Some(cookie), Some(header)) => !cookie.is_empty() && constant_time_eq(cookie.as_bytes(), header.as_bytes()),
+            _ => false,
+        }
+    }
+}
+
+impl Middleware for Csrf {
+    fn handle (
+        &self,
+        request: &mut HTTPRequest,
+        database_connections: &Arc<DatabaseConnectionPool>,
+        next: Next
+    ) -> HTTPResponse {
+        //methods, which do not change data, are not checked
+        let safe_method = matches!(
+            request.request_line.method,
+            Method::GET | Method::HEAD | Method::OPTIONS
+        );
+
+        if safe_method || request.cookie(SESSION_COOKIE).is_none() || self.check(request) {
+            return next.run(request, database_connections);
+        }
+
+        let mut response = api_error("Request has been rejected by the csrf check");
+        response.status = StatusCode::Forbidden;
+        response
+    }
+}